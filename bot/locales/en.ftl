@@ -0,0 +1,114 @@
+help-intro =
+    This is a gallery synchronization bot that is convenient for users to view pictures directly in Telegram.
+    Join develop group or contact @ByteRabbit if you need.
+
+    Bot supports sync with command, text url, or image(private chat search threshold is lower).
+
+    Bot develop group: https://t.me/TGSyncBotWorkGroup
+    And welcome to join our channel: https://t.me/sesecollection
+
+    These commands are supported:
+cmd-help = Display this help.
+cmd-version = Show bot version.
+cmd-id = Show your account id.
+cmd-sync = Sync a gallery(e-hentai/exhentai/nhentai are supported now).
+cmd-settings = Show or change settings of this chat(group admins only).
+cmd-lang = Show or change your language.
+
+id-reply = Current chat id is { $id } (in private chat this is your account id)
+unrecognized = Unrecognized message.
+
+sync-usage = Usage: /sync url
+syncing = Syncing url { $url }
+resyncing = Resyncing url { $url }
+sync-finished = Sync to telegraph finished: { $page }
+sync-failed = Sync to telegraph failed: { $error }
+resync-finished = Resync to telegraph finished: { $page }
+resync-failed = Resync to telegraph failed: { $error }
+source-found = Source found by { $engine }: { $source }
+limited = Too many sync requests, please try again in { $wait }.
+inline-syncing = Not synced yet, syncing now. Please retry later.
+batch-syncing = Syncing { $total } links, { $done } done
+batch-finished = Sync finished: { $succeeded } succeeded, { $failed } failed
+batch-truncated = Only the first { $max } links are synced.
+
+button-open = Open original
+button-resync = Resync
+button-delete = Delete cache
+button-report = Report broken
+button-archive = Download archive
+callback-admin-only = Only admins can do this.
+callback-message-missing = The message is no longer available.
+callback-resyncing = Resyncing.
+callback-deleted = Cache deleted.
+callback-delete-failed = Delete cache failed: { $error }
+callback-reported = Reported to admins, thanks.
+callback-archiving = Preparing the archive, it will be sent when ready.
+archive-too-large = The archive is larger than the Telegram file size limit.
+archive-failed = Export archive failed: { $error }
+
+settings-disabled = Settings are not enabled.
+settings-load-failed = Get settings failed: { $error }
+settings-save-failed = Save settings failed: { $error }
+settings-admin-only = Only admins of this chat can change settings.
+settings-usage =
+    Usage:
+    /settings - show current settings
+    /settings photo on|off - search photos sent to the chat
+    /settings link on|off - sync links detected in messages
+    /settings similarity 0-100|default - min similarity of photo search
+    /settings language name|default - preferred gallery language(like chinese)
+settings-photo = Photo search: { $value }
+settings-link = Link detection: { $value }
+settings-similarity = Similarity: { $value }
+settings-language = Language: { $value }
+settings-default = { $value } (default)
+settings-not-set = not set
+settings-on = on
+settings-off = off
+settings-error-switch = Value must be on or off.
+settings-error-similarity = Similarity must be between 0 and 100.
+settings-error-language = Language is required.
+settings-error-unknown = Unknown setting.
+
+lang-current = Current language: { $lang }
+lang-usage = Usage: /lang en|zh|ja
+lang-set = Language is set to { $lang }.
+lang-failed = Set language failed: { $error }
+
+admin-deleted = Key { $key } deleted.
+admin-cached = Cached: { $page }
+admin-not-cached = Url { $url } is not cached.
+admin-cache-failed = Get cache failed: { $error }
+admin-purged = Cache of url { $url } deleted.
+admin-purge-failed = Delete cache failed: { $error }
+admin-resync-usage = Usage: /resync url
+admin-cache-stats =
+    Cache hits: { $hits }
+    Cache misses: { $misses }
+    Hit rate: { $rate }%
+admin-report =
+    Broken gallery reported by { $user } ({ $id }): { $url }
+    Telegraph: { $page }
+admin-report-not-cached = not cached
+
+stats-usage = Usage: /stats [day|week|all]
+stats-failed = Load stats failed: { $error }
+stats-title = Stats ({ $period })
+stats-syncs = Syncs: { $total } ({ $list })
+stats-cache = Cache: { $hits } hits, { $misses } misses, hit rate { $rate }%
+stats-uploaded = Uploaded: { $images } images, { $size }
+stats-search = Search hits: { $list }
+stats-failures = Failures: { $list }
+stats-chats = Top chats: { $list }
+stats-none = none
+
+acl-disabled = ACL is not enabled.
+acl-private = Private mode: { $value }
+acl-set-usage = Usage: /allow id or /deny id
+acl-set = Rule of { $id } is set to { $rule }.
+acl-set-failed = Set rule failed: { $error }
+acl-unset-usage = Usage: /unset id
+acl-removed = Rule of { $id } is removed.
+acl-removed-config = Rule of { $id } is removed, { $rule } in config applies.
+acl-remove-failed = Remove rule failed: { $error }
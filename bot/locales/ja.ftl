@@ -0,0 +1,114 @@
+help-intro =
+    Telegram で直接画像を閲覧できるギャラリー同期ボットです。
+    何かあれば開発グループに参加するか @ByteRabbit に連絡してください。
+
+    コマンド、URL のテキスト、または画像で同期できます(プライベートチャットでは検索の類似度しきい値が低くなります)。
+
+    ボット開発グループ: https://t.me/TGSyncBotWorkGroup
+    作者のチャンネル: https://t.me/sesecollection
+
+    利用できるコマンド:
+cmd-help = このヘルプを表示します。
+cmd-version = ボットのバージョンを表示します。
+cmd-id = あなたのアカウント ID を表示します。
+cmd-sync = ギャラリーを同期します(現在 e-hentai/exhentai/nhentai に対応)。
+cmd-settings = このチャットの設定を表示・変更します(グループ管理者のみ)。
+cmd-lang = 言語を表示・変更します。
+
+id-reply = 現在のチャット ID は { $id } です(プライベートチャットではあなたのアカウント ID です)
+unrecognized = 認識できないメッセージです。
+
+sync-usage = 使い方: /sync URL
+syncing = { $url } を同期しています
+resyncing = { $url } を再同期しています
+sync-finished = telegraph への同期が完了しました: { $page }
+sync-failed = telegraph への同期に失敗しました: { $error }
+resync-finished = telegraph への再同期が完了しました: { $page }
+resync-failed = telegraph への再同期に失敗しました: { $error }
+source-found = { $engine } で出典が見つかりました: { $source }
+limited = 同期リクエストが多すぎます。{ $wait } 後に再試行してください。
+inline-syncing = まだ同期されていません。同期中なので後で再試行してください。
+batch-syncing = { $total } 件のリンクを同期しています({ $done } 件完了)
+batch-finished = 同期が完了しました: 成功 { $succeeded } 件、失敗 { $failed } 件
+batch-truncated = 最初の { $max } 件のリンクのみ同期します。
+
+button-open = 元のページを開く
+button-resync = 再同期
+button-delete = キャッシュを削除
+button-report = リンク切れを報告
+button-archive = アーカイブをダウンロード
+callback-admin-only = 管理者のみ実行できます。
+callback-message-missing = メッセージが見つかりません。
+callback-resyncing = 再同期しています。
+callback-deleted = キャッシュを削除しました。
+callback-delete-failed = キャッシュの削除に失敗しました: { $error }
+callback-reported = 管理者に報告しました。ありがとうございます。
+callback-archiving = アーカイブを作成中です。完了したら送信します。
+archive-too-large = アーカイブが Telegram のファイルサイズ上限を超えています。
+archive-failed = アーカイブの作成に失敗しました: { $error }
+
+settings-disabled = 設定は有効になっていません。
+settings-load-failed = 設定の読み込みに失敗しました: { $error }
+settings-save-failed = 設定の保存に失敗しました: { $error }
+settings-admin-only = このチャットの管理者のみ設定を変更できます。
+settings-usage =
+    使い方:
+    /settings - 現在の設定を表示
+    /settings photo on|off - チャットに送られた画像を検索
+    /settings link on|off - メッセージ内のリンクを同期
+    /settings similarity 0-100|default - 画像検索の最低類似度
+    /settings language 言語|default - 優先するギャラリーの言語(chinese など)
+settings-photo = 画像検索: { $value }
+settings-link = リンク検出: { $value }
+settings-similarity = 類似度: { $value }
+settings-language = 言語: { $value }
+settings-default = { $value } (デフォルト)
+settings-not-set = 未設定
+settings-on = オン
+settings-off = オフ
+settings-error-switch = 値は on または off にしてください。
+settings-error-similarity = 類似度は 0 から 100 の間にしてください。
+settings-error-language = 言語を指定してください。
+settings-error-unknown = 不明な設定です。
+
+lang-current = 現在の言語: { $lang }
+lang-usage = 使い方: /lang en|zh|ja
+lang-set = 言語を { $lang } に設定しました。
+lang-failed = 言語の設定に失敗しました: { $error }
+
+admin-deleted = キー { $key } を削除しました。
+admin-cached = キャッシュ済み: { $page }
+admin-not-cached = URL { $url } はキャッシュされていません。
+admin-cache-failed = キャッシュの取得に失敗しました: { $error }
+admin-purged = URL { $url } のキャッシュを削除しました。
+admin-purge-failed = キャッシュの削除に失敗しました: { $error }
+admin-resync-usage = 使い方: /resync URL
+admin-cache-stats =
+    キャッシュヒット: { $hits }
+    キャッシュミス: { $misses }
+    ヒット率: { $rate }%
+admin-report =
+    { $user } ({ $id }) がリンク切れのギャラリーを報告しました: { $url }
+    Telegraph: { $page }
+admin-report-not-cached = キャッシュなし
+
+stats-usage = 使い方: /stats [day|week|all]
+stats-failed = 統計の読み込みに失敗しました: { $error }
+stats-title = 統計 ({ $period })
+stats-syncs = 同期: { $total } ({ $list })
+stats-cache = キャッシュ: ヒット { $hits }、ミス { $misses }、ヒット率 { $rate }%
+stats-uploaded = アップロード: 画像 { $images } 枚、{ $size }
+stats-search = 検索ヒット: { $list }
+stats-failures = 失敗: { $list }
+stats-chats = 上位チャット: { $list }
+stats-none = なし
+
+acl-disabled = アクセス制御は有効になっていません。
+acl-private = プライベートモード: { $value }
+acl-set-usage = 使い方: /allow ID または /deny ID
+acl-set = { $id } のルールを { $rule } に設定しました。
+acl-set-failed = ルールの設定に失敗しました: { $error }
+acl-unset-usage = 使い方: /unset ID
+acl-removed = { $id } のルールを削除しました。
+acl-removed-config = { $id } のルールを削除しました。設定の { $rule } が再び適用されます。
+acl-remove-failed = ルールの削除に失敗しました: { $error }
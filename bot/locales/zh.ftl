@@ -0,0 +1,114 @@
+help-intro =
+    这是一个方便用户直接在 Telegram 里看图的画廊同步机器人。
+    如有问题请在开发群里反馈或私聊 @ByteRabbit。
+
+    机器人支持通过 命令、直接发送链接、图片(私聊搜索相似度阈值会更低) 的形式同步。
+
+    Bot 开发群: https://t.me/TGSyncBotWorkGroup
+    作者的频道: https://t.me/sesecollection
+
+    目前支持这些指令:
+cmd-help = 显示这条帮助信息。
+cmd-version = 显示机器人版本。
+cmd-id = 显示你的账号 ID。
+cmd-sync = 同步一个画廊(目前支持 EH/EX/NH)。
+cmd-settings = 查看或修改本群设置(仅群管理员)。
+cmd-lang = 查看或修改你的语言。
+
+id-reply = 当前会话 ID 为 { $id } (私聊中即为你的账号 ID)
+unrecognized = 无法识别的消息。
+
+sync-usage = 用法: /sync 链接
+syncing = 正在同步 { $url }
+resyncing = 正在重新同步 { $url }
+sync-finished = 同步到 telegraph 完成: { $page }
+sync-failed = 同步到 telegraph 失败: { $error }
+resync-finished = 重新同步到 telegraph 完成: { $page }
+resync-failed = 重新同步到 telegraph 失败: { $error }
+source-found = { $engine } 找到了来源: { $source }
+limited = 同步请求过于频繁，请在 { $wait } 后重试。
+inline-syncing = 尚未同步，正在同步中，请稍后重试。
+batch-syncing = 正在同步 { $total } 个链接，已完成 { $done } 个
+batch-finished = 同步完成: 成功 { $succeeded } 个，失败 { $failed } 个
+batch-truncated = 仅同步前 { $max } 个链接。
+
+button-open = 打开原链接
+button-resync = 重新同步
+button-delete = 删除缓存
+button-report = 报告失效
+button-archive = 下载压缩包
+callback-admin-only = 仅管理员可以执行此操作。
+callback-message-missing = 找不到该消息。
+callback-resyncing = 正在重新同步。
+callback-deleted = 缓存已删除。
+callback-delete-failed = 删除缓存失败: { $error }
+callback-reported = 已报告给管理员，感谢反馈。
+callback-archiving = 正在打包，完成后会发送。
+archive-too-large = 压缩包超过了 Telegram 的文件大小限制。
+archive-failed = 导出压缩包失败: { $error }
+
+settings-disabled = 设置功能未启用。
+settings-load-failed = 读取设置失败: { $error }
+settings-save-failed = 保存设置失败: { $error }
+settings-admin-only = 仅本群管理员可以修改设置。
+settings-usage =
+    用法:
+    /settings - 查看当前设置
+    /settings photo on|off - 搜索发送到群里的图片
+    /settings link on|off - 同步消息中识别到的链接
+    /settings similarity 0-100|default - 图片搜索的最低相似度
+    /settings language 语言|default - 偏好的画廊语言(如 chinese)
+settings-photo = 图片搜索: { $value }
+settings-link = 链接识别: { $value }
+settings-similarity = 相似度: { $value }
+settings-language = 语言: { $value }
+settings-default = { $value } (默认)
+settings-not-set = 未设置
+settings-on = 开
+settings-off = 关
+settings-error-switch = 值必须为 on 或 off。
+settings-error-similarity = 相似度必须在 0 到 100 之间。
+settings-error-language = 请指定语言。
+settings-error-unknown = 未知的设置项。
+
+lang-current = 当前语言: { $lang }
+lang-usage = 用法: /lang en|zh|ja
+lang-set = 语言已设置为 { $lang }。
+lang-failed = 设置语言失败: { $error }
+
+admin-deleted = Key { $key } 已删除。
+admin-cached = 已缓存: { $page }
+admin-not-cached = 链接 { $url } 未缓存。
+admin-cache-failed = 读取缓存失败: { $error }
+admin-purged = 链接 { $url } 的缓存已删除。
+admin-purge-failed = 删除缓存失败: { $error }
+admin-resync-usage = 用法: /resync 链接
+admin-cache-stats =
+    缓存命中: { $hits }
+    缓存未命中: { $misses }
+    命中率: { $rate }%
+admin-report =
+    { $user } ({ $id }) 报告了失效的画廊: { $url }
+    Telegraph: { $page }
+admin-report-not-cached = 未缓存
+
+stats-usage = 用法: /stats [day|week|all]
+stats-failed = 读取统计失败: { $error }
+stats-title = 统计 ({ $period })
+stats-syncs = 同步: { $total } ({ $list })
+stats-cache = 缓存: 命中 { $hits }，未命中 { $misses }，命中率 { $rate }%
+stats-uploaded = 上传: { $images } 张图片，{ $size }
+stats-search = 搜索命中: { $list }
+stats-failures = 失败: { $list }
+stats-chats = 活跃会话: { $list }
+stats-none = 无
+
+acl-disabled = 访问控制未启用。
+acl-private = 私有模式: { $value }
+acl-set-usage = 用法: /allow id 或 /deny id
+acl-set = { $id } 的规则已设置为 { $rule }。
+acl-set-failed = 设置规则失败: { $error }
+acl-unset-usage = 用法: /unset id
+acl-removed = { $id } 的规则已删除。
+acl-removed-config = { $id } 的规则已删除，配置中的 { $rule } 重新生效。
+acl-remove-failed = 删除规则失败: { $error }
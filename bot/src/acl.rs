@@ -0,0 +1,191 @@
+//! Access control lists.
+//! Ids in lists match both chat ids and user ids since they never collide
+//! (ids of groups and channels are negative).
+//! Rules changed at runtime are saved in storage and override the config.
+use std::collections::HashMap;
+
+use eh2telegraph::storage::KVStorage;
+use parking_lot::RwLock;
+
+pub const CONFIG_KEY: &str = "acl";
+
+const KEY_PREFIX: &str = "acl|";
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct AclConfig {
+    /// Only serve chats and users in the allowlist.
+    #[serde(default)]
+    pub private: bool,
+    #[serde(default)]
+    pub allow: Vec<i64>,
+    #[serde(default)]
+    pub deny: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    Allow,
+    Deny,
+}
+
+impl Rule {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Rule::Allow => "allow",
+            Rule::Deny => "deny",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "allow" => Some(Rule::Allow),
+            "deny" => Some(Rule::Deny),
+            _ => None,
+        }
+    }
+}
+
+pub struct Acl<S> {
+    private: bool,
+    /// Rules of the config, restored when runtime rules are removed.
+    config_rules: HashMap<i64, Rule>,
+    rules: RwLock<HashMap<i64, Rule>>,
+    storage: S,
+}
+
+impl<S> Acl<S>
+where
+    S: KVStorage<String>,
+{
+    pub fn new(config: AclConfig, storage: S) -> Self {
+        let rules: HashMap<_, _> = config
+            .allow
+            .into_iter()
+            .map(|id| (id, Rule::Allow))
+            // deny wins if an id is in both lists
+            .chain(config.deny.into_iter().map(|id| (id, Rule::Deny)))
+            .collect();
+        Self {
+            private: config.private,
+            config_rules: rules.clone(),
+            rules: RwLock::new(rules),
+            storage,
+        }
+    }
+
+    /// Load rules saved at runtime.
+    pub async fn load(&self) -> anyhow::Result<()> {
+        let keys = self.storage.scan(KEY_PREFIX).await?;
+        for info in keys {
+            let key = info.key;
+            let id = match key
+                .strip_prefix(KEY_PREFIX)
+                .and_then(|id| id.parse::<i64>().ok())
+            {
+                Some(id) => id,
+                None => continue,
+            };
+            if let Some(rule) = self
+                .storage
+                .get(&key)
+                .await?
+                .as_deref()
+                .and_then(Rule::parse)
+            {
+                self.rules.write().insert(id, rule);
+            }
+        }
+        tracing::info!("[acl] loaded {} rules", self.rules.read().len());
+        Ok(())
+    }
+
+    /// Set the rule of the id and save it.
+    pub async fn set(&self, id: i64, rule: Rule) -> anyhow::Result<()> {
+        self.storage
+            .set(format!("{KEY_PREFIX}{id}"), rule.as_str().to_string(), None)
+            .await?;
+        self.rules.write().insert(id, rule);
+        Ok(())
+    }
+
+    /// Remove the saved rule of the id, the rule in config applies again.
+    /// Returns the rule of the id after removing.
+    pub async fn remove(&self, id: i64) -> anyhow::Result<Option<Rule>> {
+        self.storage.delete(&format!("{KEY_PREFIX}{id}")).await?;
+        let rule = self.config_rules.get(&id).copied();
+        let mut rules = self.rules.write();
+        match rule {
+            Some(r) => rules.insert(id, r),
+            None => rules.remove(&id),
+        };
+        Ok(rule)
+    }
+
+    /// Denied if any id is denied. In private mode, at least one id must be allowed.
+    pub fn is_allowed(&self, ids: &[i64]) -> bool {
+        let rules = self.rules.read();
+        let mut allowed = !self.private;
+        for id in ids {
+            match rules.get(id) {
+                Some(Rule::Deny) => return false,
+                Some(Rule::Allow) => allowed = true,
+                None => (),
+            }
+        }
+        allowed
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.private
+    }
+
+    /// All rules sorted by id.
+    pub fn list(&self) -> Vec<(i64, Rule)> {
+        let mut rules: Vec<_> = self.rules.read().iter().map(|(k, v)| (*k, *v)).collect();
+        rules.sort_unstable_by_key(|(id, _)| *id);
+        rules
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eh2telegraph::storage::SimpleMemStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn acl() {
+        let storage = SimpleMemStorage::default();
+        let config = AclConfig {
+            private: true,
+            allow: vec![1, -100],
+            deny: vec![2],
+        };
+        let acl = Acl::new(config.clone(), storage.clone());
+        assert!(acl.is_allowed(&[1]));
+        assert!(acl.is_allowed(&[3, -100]));
+        assert!(!acl.is_allowed(&[2, -100]));
+        assert!(!acl.is_allowed(&[3]));
+
+        acl.set(3, Rule::Allow).await.unwrap();
+        acl.set(1, Rule::Deny).await.unwrap();
+        assert!(acl.is_allowed(&[3]));
+        assert!(!acl.is_allowed(&[1]));
+
+        // runtime rules override config
+        let acl = Acl::new(config.clone(), storage.clone());
+        acl.load().await.unwrap();
+        assert!(acl.is_allowed(&[3]));
+        assert!(!acl.is_allowed(&[1]));
+        assert_eq!(acl.list().len(), 4);
+
+        // removed rules fall back to config
+        assert_eq!(acl.remove(1).await.unwrap(), Some(Rule::Allow));
+        assert_eq!(acl.remove(3).await.unwrap(), None);
+        assert!(acl.is_allowed(&[1]));
+        assert!(!acl.is_allowed(&[3]));
+        let acl = Acl::new(config, storage);
+        acl.load().await.unwrap();
+        assert_eq!(acl.list().len(), 3);
+    }
+}
@@ -0,0 +1,319 @@
+//! Admin alerts.
+//! Unexpected sync and search errors are classified and sent to admins.
+//! The same alert is sent once per cooldown and alerts are limited per hour,
+//! suppressed ones only show up in the daily digest.
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use eh2telegraph::{collector::InputError, sync::UploadError, telegraph::TelegraphError};
+use parking_lot::Mutex;
+use teloxide::{
+    adaptors::DefaultParseMode,
+    prelude2::*,
+    utils::markdown::{bold, code_inline, escape},
+};
+
+use crate::util::truncate;
+
+pub const CONFIG_KEY: &str = "alert";
+
+pub const DIGEST_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const WINDOW: Duration = Duration::from_secs(60 * 60);
+// keep alerts short, the full error is in logs
+const MAX_MESSAGE_LEN: usize = 512;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AlertConfig {
+    /// Seconds before the same alert can be sent again.
+    #[serde(default = "default_cooldown")]
+    pub cooldown: u64,
+    #[serde(default = "default_max_per_hour")]
+    pub max_per_hour: usize,
+    #[serde(default = "default_true")]
+    pub digest: bool,
+}
+
+fn default_cooldown() -> u64 {
+    60 * 60
+}
+
+fn default_max_per_hour() -> usize {
+    10
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Category {
+    /// Telegraph rejected the access token.
+    TelegraphAuth,
+    Telegraph,
+    /// Images can not be downloaded while uploading.
+    Upload,
+    /// Gallery pages can not be fetched or parsed, like expired cookies or
+    /// blocked ip.
+    Collector,
+    /// All search engines failed, like unexpected SauceNAO responses.
+    Search,
+}
+
+impl Category {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Category::TelegraphAuth => "telegraph token",
+            Category::Telegraph => "telegraph",
+            Category::Upload => "upload",
+            Category::Collector => "collector",
+            Category::Search => "search",
+        }
+    }
+
+    /// Classify the sync error. Returns None for errors caused by users.
+    pub fn of_sync(err: &anyhow::Error) -> Option<Self> {
+        if let Some(e) = err.chain().find_map(|e| e.downcast_ref::<TelegraphError>()) {
+            return Some(match e {
+                TelegraphError::Api(msg) if msg.contains("ACCESS_TOKEN") => Category::TelegraphAuth,
+                _ => Category::Telegraph,
+            });
+        }
+        if err.chain().any(|e| e.is::<InputError>()) {
+            return None;
+        }
+        if err.chain().any(|e| e.is::<UploadError<anyhow::Error>>()) {
+            return Some(Category::Upload);
+        }
+        Some(Category::Collector)
+    }
+
+    /// Classify the search error. Errors of Telegram are not alerted.
+    pub fn of_search(err: &anyhow::Error) -> Option<Self> {
+        let telegram = err
+            .chain()
+            .any(|e| e.is::<teloxide::RequestError>() || e.is::<teloxide::DownloadError>());
+        if telegram {
+            None
+        } else {
+            Some(Category::Search)
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Count {
+    total: u64,
+    suppressed: u64,
+    last: String,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Last sent time of alerts by fingerprint.
+    sent: HashMap<(Category, String), Instant>,
+    /// Sent time of alerts in the last hour.
+    window: VecDeque<Instant>,
+    /// Counts since the last digest.
+    counts: HashMap<Category, Count>,
+}
+
+pub struct Alerter {
+    bot: AutoSend<DefaultParseMode<Bot>>,
+    admins: Vec<i64>,
+    cooldown: Duration,
+    max_per_hour: usize,
+    pub digest: bool,
+    state: Mutex<State>,
+}
+
+impl Alerter {
+    pub fn new(
+        bot: AutoSend<DefaultParseMode<Bot>>,
+        config: AlertConfig,
+        admins: Vec<i64>,
+    ) -> Self {
+        Self {
+            bot,
+            admins,
+            cooldown: Duration::from_secs(config.cooldown),
+            max_per_hour: config.max_per_hour,
+            digest: config.digest,
+            state: Default::default(),
+        }
+    }
+
+    /// Record the error and send it to admins in background if not suppressed.
+    pub fn report(&self, category: Category, context: &str, err: &anyhow::Error) {
+        let message = truncate(&err.to_string(), MAX_MESSAGE_LEN);
+        if !self.record(category, &message, Instant::now()) {
+            tracing::info!("[alert] suppressed {} alert", category.as_str());
+            return;
+        }
+        let text = format!(
+            "⚠️ {}\n{}\n{}",
+            bold(&escape(&format!("Alert: {}", category.as_str()))),
+            escape(context),
+            code_inline(&message)
+        );
+        self.send(text);
+    }
+
+    /// Returns true if the alert should be sent.
+    fn record(&self, category: Category, message: &str, now: Instant) -> bool {
+        let mut state = self.state.lock();
+        let count = state.counts.entry(category).or_default();
+        count.total += 1;
+        count.last = message.to_string();
+
+        let key = (category, fingerprint(message));
+        let cooling = state
+            .sent
+            .get(&key)
+            .map(|t| now.saturating_duration_since(*t) < self.cooldown)
+            .unwrap_or_default();
+        while let Some(t) = state.window.front() {
+            if now.saturating_duration_since(*t) < WINDOW {
+                break;
+            }
+            state.window.pop_front();
+        }
+        if cooling || state.window.len() >= self.max_per_hour {
+            if let Some(count) = state.counts.get_mut(&category) {
+                count.suppressed += 1;
+            }
+            return false;
+        }
+        state.window.push_back(now);
+        // alerts out of cooldown are the same as never sent ones
+        let cooldown = self.cooldown;
+        state
+            .sent
+            .retain(|_, t| now.saturating_duration_since(*t) < cooldown);
+        state.sent.insert(key, now);
+        true
+    }
+
+    /// Send the digest of errors since the last one. Nothing is sent if there
+    /// is no error.
+    pub fn send_digest(&self) {
+        let counts = std::mem::take(&mut self.state.lock().counts);
+        if let Some(text) = digest(counts) {
+            self.send(text);
+        }
+    }
+
+    fn send(&self, text: String) {
+        let bot = self.bot.clone();
+        let admins = self.admins.clone();
+        tokio::spawn(async move {
+            for admin in admins {
+                if let Err(e) = bot
+                    .send_message(admin, text.clone())
+                    .disable_web_page_preview(true)
+                    .await
+                {
+                    tracing::error!("[alert] unable to send alert to {admin}: {e:?}");
+                }
+            }
+        });
+    }
+}
+
+fn digest(counts: HashMap<Category, Count>) -> Option<String> {
+    if counts.is_empty() {
+        return None;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_unstable_by_key(|(c, _)| *c);
+    let mut text = bold("Daily error digest");
+    for (category, count) in counts {
+        text.push_str(&format!(
+            "\n{}: {} \\({} suppressed\\)\n{}",
+            bold(&escape(category.as_str())),
+            count.total,
+            count.suppressed,
+            code_inline(&count.last)
+        ));
+    }
+    Some(text)
+}
+
+/// Urls and numbers are ignored so the same error of different galleries is
+/// deduplicated.
+fn fingerprint(message: &str) -> String {
+    message
+        .split_whitespace()
+        .map(|w| {
+            if w.contains("://") {
+                "<url>".to_string()
+            } else {
+                w.chars()
+                    .map(|c| if c.is_ascii_digit() { '#' } else { c })
+                    .collect()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify() {
+        let e = anyhow::Error::from(TelegraphError::Api("ACCESS_TOKEN_INVALID".to_string()));
+        assert_eq!(Category::of_sync(&e), Some(Category::TelegraphAuth));
+        let e = anyhow::Error::from(TelegraphError::Server);
+        assert_eq!(Category::of_sync(&e), Some(Category::Telegraph));
+        let e = anyhow::Error::from(UploadError::<anyhow::Error>::Stream(anyhow::anyhow!(
+            "unable to find image in page"
+        )));
+        assert_eq!(Category::of_sync(&e), Some(Category::Upload));
+        // messages are not checked
+        let e = anyhow::anyhow!("stream error unable to find image in page");
+        assert_eq!(Category::of_sync(&e), Some(Category::Collector));
+        let e = anyhow::Error::from(InputError(
+            "invalid url, maybe resource has been deleted.".to_string(),
+        ));
+        assert_eq!(Category::of_sync(&e), None);
+        let e = anyhow::Error::from(InputError("Invalid url".to_string())).context("sync failed");
+        assert_eq!(Category::of_sync(&e), None);
+
+        let e = anyhow::anyhow!("saucenao response is not as expected");
+        assert_eq!(Category::of_search(&e), Some(Category::Search));
+    }
+
+    #[test]
+    fn dedupe() {
+        assert_eq!(
+            fingerprint("error sending request for url (https://a.b/1) page 12"),
+            "error sending request for url <url> page ##"
+        );
+
+        let bot = Bot::new("").parse_mode(teloxide::types::ParseMode::MarkdownV2);
+        let config = AlertConfig {
+            cooldown: 60,
+            max_per_hour: 2,
+            digest: true,
+        };
+        let alerter = Alerter::new(bot.auto_send(), config, vec![]);
+        let now = Instant::now();
+        assert!(alerter.record(Category::Collector, "page 1", now));
+        // same fingerprint in cooldown
+        assert!(!alerter.record(Category::Collector, "page 2", now));
+        assert!(alerter.record(Category::Collector, "page 2", now + Duration::from_secs(61)));
+        // rate limited
+        assert!(!alerter.record(Category::Search, "failed", now + Duration::from_secs(62)));
+        assert!(alerter.record(Category::Search, "failed", now + WINDOW));
+        // expired alerts are dropped
+        assert_eq!(alerter.state.lock().sent.len(), 1);
+
+        let text = digest(std::mem::take(&mut alerter.state.lock().counts)).unwrap();
+        assert!(text.contains("*collector*: 3 \\(1 suppressed\\)"));
+        assert!(text.contains("*search*: 2 \\(1 suppressed\\)"));
+        assert!(digest(HashMap::new()).is_none());
+    }
+}
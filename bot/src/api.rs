@@ -0,0 +1,358 @@
+//! HTTP API for other tools to sync and search.
+//! It shares the synchronizer, searchers and storage with the bot.
+//! Requests must carry one of the configured keys in `Authorization: Bearer <key>`.
+//!
+//! - `POST /sync` with `{"url": "...", "force": false}` starts a sync job
+//! - `GET /jobs/{id}` returns status and progress of the job
+//! - `POST /search` with the image as body returns search hits
+//! - `GET /cache?url=...` returns the cache record of the gallery
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use eh2telegraph::{searcher::ImageSearcher, storage::KVStorage, sync::Progress};
+use hyper::{
+    header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE},
+    server::conn::Http,
+    service::service_fn,
+    Body, HeaderMap, Method, Request, Response, StatusCode,
+};
+use parking_lot::{Mutex, RwLock};
+use reqwest::Url;
+use tokio::net::TcpListener;
+
+use crate::{
+    handler::Handler,
+    util::{constant_time_eq, read_body},
+};
+
+pub const CONFIG_KEY: &str = "api";
+
+// finished jobs are kept for querying
+const JOB_TTL: Duration = Duration::from_secs(60 * 60);
+const MAX_RUNNING_JOBS: usize = 32;
+const MAX_SYNC_REQUEST_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ApiConfig {
+    #[serde(default = "default_listen")]
+    pub listen: SocketAddr,
+    /// Keys accepted in `Authorization: Bearer <key>`.
+    pub keys: Vec<String>,
+    /// Max size of images in bytes for searching.
+    #[serde(default = "default_max_image_size")]
+    pub max_image_size: u64,
+}
+
+fn default_listen() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], 8081))
+}
+
+fn default_max_image_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+enum JobState {
+    Running,
+    Done { telegraph: String },
+    Failed { error: String },
+}
+
+struct Job {
+    url: String,
+    state: Mutex<JobState>,
+    progress: Arc<Progress>,
+    finished: Mutex<Option<Instant>>,
+}
+
+#[derive(serde::Serialize)]
+struct JobOutput<'a> {
+    id: &'a str,
+    url: &'a str,
+    #[serde(flatten)]
+    state: JobState,
+    uploaded: usize,
+    total: usize,
+}
+
+#[derive(serde::Deserialize)]
+struct SyncRequest {
+    url: String,
+    #[serde(default)]
+    force: bool,
+}
+
+struct State<C: 'static> {
+    handler: &'static Handler<C>,
+    keys: Vec<String>,
+    max_image_size: u64,
+    jobs: RwLock<HashMap<String, Arc<Job>>>,
+}
+
+/// Start the api server in background.
+pub async fn serve<C>(
+    config: &ApiConfig,
+    handler: &'static Handler<C>,
+) -> anyhow::Result<SocketAddr>
+where
+    C: KVStorage<String> + Send + Sync + 'static,
+{
+    if config.keys.is_empty() {
+        return Err(anyhow::anyhow!("api keys can not be empty"));
+    }
+    let state = Arc::new(State {
+        handler,
+        keys: config.keys.clone(),
+        max_image_size: config.max_image_size,
+        jobs: Default::default(),
+    });
+    let listener = TcpListener::bind(config.listen).await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::error!("[api] accept fail: {e}");
+                    continue;
+                }
+            };
+            let state = state.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |req| handle(state.clone(), req));
+                if let Err(e) = Http::new().serve_connection(stream, service).await {
+                    tracing::debug!("[api] serve connection fail: {e}");
+                }
+            });
+        }
+    });
+    tracing::info!("[api] listening on {addr}");
+    Ok(addr)
+}
+
+async fn handle<C>(state: Arc<State<C>>, req: Request<Body>) -> Result<Response<Body>, Infallible>
+where
+    C: KVStorage<String> + Send + Sync + 'static,
+{
+    if !authorized(req.headers(), &state.keys) {
+        return Ok(error(StatusCode::UNAUTHORIZED, "invalid api key"));
+    }
+    let path = req.uri().path().to_string();
+    let resp = match (req.method(), path.as_str()) {
+        (&Method::POST, "/sync") => start_sync(&state, req).await,
+        (&Method::GET, p) if p.starts_with("/jobs/") => get_job(&state, &p["/jobs/".len()..]),
+        (&Method::POST, "/search") => search(&state, req).await,
+        (&Method::GET, "/cache") => get_cache(&state, &req).await,
+        _ => error(StatusCode::NOT_FOUND, "not found"),
+    };
+    Ok(resp)
+}
+
+fn authorized(headers: &HeaderMap, keys: &[String]) -> bool {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        // every key is compared in constant time, so timing does not leak the keys
+        .map(|key| {
+            keys.iter().fold(false, |ok, k| {
+                ok | constant_time_eq(k.as_bytes(), key.as_bytes())
+            })
+        })
+        .unwrap_or_default()
+}
+
+async fn start_sync<C>(state: &Arc<State<C>>, req: Request<Body>) -> Response<Body>
+where
+    C: KVStorage<String> + Send + Sync + 'static,
+{
+    let body = match read_body(req.into_body(), MAX_SYNC_REQUEST_SIZE).await {
+        Ok(Some(b)) => b,
+        Ok(None) => return error(StatusCode::PAYLOAD_TOO_LARGE, "request is too large"),
+        Err(e) => return error(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+    let SyncRequest { url, force } = match serde_json::from_slice(&body) {
+        Ok(r) => r,
+        Err(e) => return error(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+    // reject urls without matching collector before starting the job
+    if let Err(e) = state.handler.synchronizer.get_record_url(&url).await {
+        return error(StatusCode::BAD_REQUEST, &e.to_string());
+    }
+
+    let id = format!("{:016x}", rand::random::<u64>());
+    let job = Arc::new(Job {
+        url,
+        state: Mutex::new(JobState::Running),
+        progress: Default::default(),
+        finished: Mutex::new(None),
+    });
+    {
+        let mut jobs = state.jobs.write();
+        jobs.retain(|_, j| {
+            j.finished
+                .lock()
+                .map(|t| t.elapsed() < JOB_TTL)
+                .unwrap_or(true)
+        });
+        let running = jobs
+            .values()
+            .filter(|j| j.finished.lock().is_none())
+            .count();
+        if running >= MAX_RUNNING_JOBS {
+            return error(StatusCode::TOO_MANY_REQUESTS, "too many running jobs");
+        }
+        jobs.insert(id.clone(), job.clone());
+    }
+    tracing::info!("[api] start job {id} for {}", job.url);
+
+    let handler = state.handler;
+    let running = job.clone();
+    tokio::spawn(async move {
+        // failures are alerted by the handler
+        let r = handler
+            .sync_with_progress(&running.url, force, None, Some(running.progress.clone()))
+            .await;
+        let new_state = match r {
+            Ok(telegraph) => JobState::Done { telegraph },
+            Err(error) => JobState::Failed { error },
+        };
+        *running.state.lock() = new_state;
+        *running.finished.lock() = Some(Instant::now());
+    });
+
+    let mut resp = json(&job_output(&id, &job));
+    *resp.status_mut() = StatusCode::ACCEPTED;
+    resp
+}
+
+fn get_job<C: 'static>(state: &State<C>, id: &str) -> Response<Body> {
+    match state.jobs.read().get(id) {
+        Some(job) => json(&job_output(id, job)),
+        None => error(StatusCode::NOT_FOUND, "job not found"),
+    }
+}
+
+fn job_output<'a>(id: &'a str, job: &'a Job) -> JobOutput<'a> {
+    let (uploaded, total) = job.progress.get();
+    JobOutput {
+        id,
+        url: &job.url,
+        state: job.state.lock().clone(),
+        uploaded,
+        total,
+    }
+}
+
+async fn search<C>(state: &State<C>, req: Request<Body>) -> Response<Body>
+where
+    C: KVStorage<String> + Send + Sync + 'static,
+{
+    let size = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    match size {
+        None => return error(StatusCode::LENGTH_REQUIRED, "content length is required"),
+        Some(s) if s > state.max_image_size => {
+            return error(StatusCode::PAYLOAD_TOO_LARGE, "image is too large")
+        }
+        _ => (),
+    }
+    let body = match read_body(req.into_body(), state.max_image_size as usize).await {
+        Ok(Some(b)) => b,
+        Ok(None) => return error(StatusCode::PAYLOAD_TOO_LARGE, "image is too large"),
+        Err(e) => return error(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+    match state.handler.searcher.search(body).await {
+        Ok(hits) => json(&serde_json::json!({ "hits": hits })),
+        Err(e) => error(StatusCode::BAD_GATEWAY, &e.to_string()),
+    }
+}
+
+async fn get_cache<C>(state: &State<C>, req: &Request<Body>) -> Response<Body>
+where
+    C: KVStorage<String> + Send + Sync + 'static,
+{
+    let url = match query_param(req.uri().query().unwrap_or_default(), "url") {
+        Some(u) => u,
+        None => return error(StatusCode::BAD_REQUEST, "url is required"),
+    };
+    match state.handler.synchronizer.get_record_url(&url).await {
+        Ok(record) => json(&serde_json::json!({ "url": url, "record": record })),
+        Err(e) => error(StatusCode::BAD_REQUEST, &e.to_string()),
+    }
+}
+
+fn query_param(query: &str, key: &str) -> Option<String> {
+    Url::parse(&format!("http://localhost/?{query}"))
+        .ok()?
+        .query_pairs()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.into_owned())
+}
+
+fn json<T: serde::Serialize>(value: &T) -> Response<Body> {
+    let mut resp = Response::new(Body::from(
+        serde_json::to_string(value).expect("unable to serialize response"),
+    ));
+    resp.headers_mut().insert(
+        CONTENT_TYPE,
+        "application/json".parse().expect("invalid header"),
+    );
+    resp
+}
+
+fn error(code: StatusCode, message: &str) -> Response<Body> {
+    let mut resp = json(&serde_json::json!({ "error": message }));
+    *resp.status_mut() = code;
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auth() {
+        let keys = vec!["other".to_string(), "secret".to_string()];
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(&headers, &keys));
+        headers.insert(AUTHORIZATION, "Bearer wrong".parse().unwrap());
+        assert!(!authorized(&headers, &keys));
+        headers.insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert!(authorized(&headers, &keys));
+    }
+
+    #[test]
+    fn query() {
+        assert_eq!(
+            query_param("url=https%3A%2F%2Fe-hentai.org%2Fg%2F1%2Fa%2F&x=1", "url").as_deref(),
+            Some("https://e-hentai.org/g/1/a/")
+        );
+        assert_eq!(query_param("x=1", "url"), None);
+    }
+
+    #[test]
+    fn job() {
+        let job = Job {
+            url: "https://e-hentai.org/g/1/a/".to_string(),
+            state: Mutex::new(JobState::Done {
+                telegraph: "https://telegra.ph/a".to_string(),
+            }),
+            progress: Default::default(),
+            finished: Mutex::new(None),
+        };
+        let output = serde_json::to_value(job_output("1", &job)).unwrap();
+        assert_eq!(output["status"], "done");
+        assert_eq!(output["telegraph"], "https://telegra.ph/a");
+        assert_eq!(output["total"], 0);
+    }
+}
@@ -1,58 +1,89 @@
-use std::{borrow::Cow, collections::HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use eh2telegraph::{
-    collector::{e_hentai::EHCollector, exhentai::EXCollector, nhentai::NHCollector},
+    archive::{ArchiveError, CbzWriter},
     searcher::{
+        aggregate::AggregatedSearcher,
         f_hash::FHashConvertor,
-        saucenao::{SaucenaoOutput, SaucenaoParsed, SaucenaoSearcher},
-        ImageSearcher,
+        resolver::{GalleryResolver, ResolverChain},
+        ImageSearcher, SearchHit,
     },
+    stats::{group, Counters, Period},
     storage::KVStorage,
-    sync::Synchronizer,
+    sync::{CacheRecord, Progress, Synced, Synchronizer},
 };
 
+use futures::StreamExt;
 use reqwest::Url;
 use teloxide::{
     adaptors::DefaultParseMode,
     prelude2::*,
+    types::{
+        CallbackQuery, Chat, InlineKeyboardButton, InlineKeyboardMarkup, InlineQuery,
+        InlineQueryResult, InlineQueryResultArticle, InputFile, InputMessageContent,
+        InputMessageContentText, MessageEntity, MessageEntityKind, TargetMessage, User,
+    },
     utils::{
         command::BotCommand,
-        markdown::{code_inline, escape, link},
+        markdown::{bold, code_inline, escape, link},
     },
 };
 use tracing::{info, trace};
 
-use crate::{ok_or_break, util::PrettyChat};
+use crate::{
+    acl::{Acl, Rule},
+    alert::{Alerter, Category},
+    i18n::{self, tr, tr_md, Locale, LocaleStore},
+    limiter::{format_wait, Limiter},
+    ok_or_break,
+    poster::Poster,
+    settings::{ChatSettings, SettingsStore},
+    util::{truncate, PrettyChat},
+};
 
 const MIN_SIMILARITY: u8 = 70;
 const MIN_SIMILARITY_PRIVATE: u8 = 50;
+const INLINE_RESULT_LIMIT: usize = 20;
+// max urls synced from one message
+const MAX_BATCH_SIZE: usize = 20;
+const TOP_CHATS: usize = 10;
+const BATCH_CONCURRENCY: usize = 3;
+// wait for the rest messages of a media group
+const MEDIA_GROUP_WAIT: Duration = Duration::from_millis(1500);
+// search engines are rate limited, so only some images of an album are searched
+const MAX_MEDIA_GROUP_SEARCH: usize = 5;
+const MAX_IMAGE_DOCUMENT_SIZE: u32 = 10 * 1024 * 1024;
+// bots can upload files up to 50MB, leave some room for the estimation
+const MAX_ARCHIVE_SIZE: u64 = 49 * 1024 * 1024;
+// limited by telegram
+const MAX_CALLBACK_DATA_LEN: usize = 64;
+// keep batch status under the 4096 chars limit of messages
+const MAX_BATCH_ERROR_LEN: usize = 100;
+// admins are notified once per gallery in the window
+const REPORT_WINDOW: Duration = Duration::from_secs(60 * 60);
+// file id of the sent archive is saved with the key, so it can be sent again without exporting
+const ARCHIVE_KEY_PREFIX: &str = "archive|";
 
+// Help text of commands is generated by `i18n::help` for each locale.
 #[derive(BotCommand, Clone)]
-#[command(
-    rename = "lowercase",
-    description = "\
-    This is a gallery synchronization robot that is convenient for users to view pictures directly in Telegram.\n\
-    这是一个方便用户直接在 Telegram 里看图的画廊同步机器人。\n\
-    Join develop group or contact @ByteRabbit if you need.\n\
-    如有问题请在开发群里反馈或私聊 @ByteRabbit。\n\n\
-    Bot supports sync with command, text url, or image(private chat search thrashold is lower).\n\
-    机器人支持通过 命令、直接发送链接、图片(私聊搜索相似度阈值会更低) 的形式同步。\n\n\
-    Bot develop group / Bot 开发群 https://t.me/TGSyncBotWorkGroup\n\
-    And welcome to join our channel / 作者的频道 https://t.me/sesecollection\n\n\
-    These commands are supported:\n\
-    目前支持这些指令:"
-)]
+#[command(rename = "lowercase", description = "Commands for users")]
 pub enum Command {
-    #[command(description = "Display this help. 显示这条帮助信息。")]
+    #[command(description = "Display this help.")]
     Help,
-    #[command(description = "Show bot verison. 显示机器人版本。")]
+    #[command(description = "Show bot version.")]
     Version,
-    #[command(description = "Show your account id. 显示你的账号 ID。")]
+    #[command(description = "Show your account id.")]
     Id,
-    #[command(
-        description = "Sync a gallery(e-hentai/exhentai/nhentai are supported now). 同步一个画廊(目前支持 EH/EX/NH)"
-    )]
+    #[command(description = "Sync a gallery.")]
     Sync(String),
+    #[command(description = "Show or change settings of this chat.")]
+    Settings(String),
+    #[command(description = "Show or change your language.")]
+    Lang(String),
 }
 
 #[derive(BotCommand, Clone)]
@@ -60,32 +91,197 @@ pub enum Command {
 pub enum AdminCommand {
     #[command(description = "Delete cache with given key.")]
     Delete(String),
+    #[command(description = "Show cached record of the given gallery url.")]
+    CacheGet(String),
+    #[command(description = "Delete cache of the given gallery url.")]
+    Purge(String),
+    #[command(description = "Sync the given gallery url again, bypassing cache.")]
+    Resync(String),
+    #[command(description = "Show cache hit and miss counters.")]
+    CacheStats,
+    #[command(description = "Show usage statistics of today, this week or all time.")]
+    Stats(String),
+    #[command(description = "Allow the given chat or user id.")]
+    Allow(String),
+    #[command(description = "Deny the given chat or user id.")]
+    Deny(String),
+    #[command(description = "Remove the rule of the given chat or user id set by allow or deny.")]
+    Unset(String),
+    #[command(description = "List access control rules.")]
+    ListAcl,
+}
+
+/// Actions of the buttons attached to sync results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Resync,
+    Delete,
+    Report,
+    Archive,
+}
+
+impl Action {
+    const ALL: [Self; 4] = [Self::Resync, Self::Delete, Self::Report, Self::Archive];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Resync => "resync",
+            Self::Delete => "delete",
+            Self::Report => "report",
+            Self::Archive => "archive",
+        }
+    }
+
+    /// Encode as callback data. Returns None if it is too long.
+    fn encode(self, url: &str) -> Option<String> {
+        let data = format!("{}|{url}", self.name());
+        if data.len() <= MAX_CALLBACK_DATA_LEN {
+            Some(data)
+        } else {
+            None
+        }
+    }
+
+    fn decode(data: &str) -> Option<(Self, &str)> {
+        let (name, url) = data.split_once('|')?;
+        Self::ALL
+            .into_iter()
+            .find(|a| a.name() == name)
+            .map(|a| (a, url))
+    }
+}
+
+/// Buttons attached to sync results.
+fn result_keyboard(locale: Locale, url: &str) -> InlineKeyboardMarkup {
+    let mut keyboard = InlineKeyboardMarkup::default();
+    if let Ok(u) = Url::parse(url) {
+        keyboard = keyboard.append_row(vec![InlineKeyboardButton::url(
+            tr(locale, "button-open", &[]),
+            u,
+        )]);
+    }
+    let buttons: Vec<_> = [
+        (Action::Resync, "button-resync"),
+        (Action::Delete, "button-delete"),
+        (Action::Report, "button-report"),
+        (Action::Archive, "button-archive"),
+    ]
+    .into_iter()
+    .filter_map(|(action, id)| {
+        action
+            .encode(url)
+            .map(|data| InlineKeyboardButton::callback(tr(locale, id, &[]), data))
+    })
+    .collect();
+    if !buttons.is_empty() {
+        keyboard = keyboard.append_row(buttons);
+    }
+    keyboard
 }
 
 pub struct Handler<C> {
     pub synchronizer: Synchronizer<C>,
-    pub searcher: SaucenaoSearcher,
-    pub convertor: FHashConvertor,
+    pub searcher: AggregatedSearcher,
+    pub resolver: ResolverChain,
     pub admins: HashSet<i64>,
+    /// Preferred language when an image appears in multiple galleries.
+    pub preferred_language: Option<String>,
+    pub limiter: Option<Limiter<C>>,
+    pub acl: Option<Acl<C>>,
+    pub settings: Option<SettingsStore<C>>,
+    pub locales: Option<LocaleStore<C>>,
+    pub poster: Option<Poster<C>>,
+    pub alerter: Option<Alerter>,
+    /// Storage of file ids of sent archives.
+    pub archives: Option<C>,
 
-    single_flight: singleflight_async::SingleFlight<String>,
+    single_flight: singleflight_async::SingleFlight<Result<Synced, String>>,
+    /// Messages of media groups waiting to be searched.
+    media_groups: parking_lot::Mutex<HashMap<String, Vec<Message>>>,
+    /// Galleries reported broken recently, with the time of the report.
+    reported: parking_lot::Mutex<HashMap<String, Instant>>,
 }
 
 impl<C> Handler<C>
 where
     C: KVStorage<String> + Send + Sync + 'static,
 {
-    pub fn new(synchronizer: Synchronizer<C>, admins: HashSet<i64>) -> Self {
+    pub fn new(
+        synchronizer: Synchronizer<C>,
+        searcher: AggregatedSearcher,
+        admins: HashSet<i64>,
+    ) -> Self {
         Self {
             synchronizer,
-            searcher: SaucenaoSearcher::new_from_config(),
-            convertor: FHashConvertor::new_from_config(),
+            searcher,
+            resolver: ResolverChain::new(Arc::new(FHashConvertor::new_from_config())),
             admins,
+            preferred_language: None,
+            limiter: None,
+            acl: None,
+            settings: None,
+            locales: None,
+            poster: None,
+            alerter: None,
+            archives: None,
 
             single_flight: Default::default(),
+            media_groups: Default::default(),
+            reported: Default::default(),
         }
     }
 
+    pub fn with_preferred_language(mut self, language: Option<String>) -> Self {
+        self.preferred_language = language;
+        self
+    }
+
+    pub fn with_limiter(mut self, limiter: Limiter<C>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    pub fn with_acl(mut self, acl: Acl<C>) -> Self {
+        self.acl = Some(acl);
+        self
+    }
+
+    pub fn with_settings(mut self, settings: SettingsStore<C>) -> Self {
+        self.settings = Some(settings);
+        self
+    }
+
+    pub fn with_locales(mut self, locales: LocaleStore<C>) -> Self {
+        self.locales = Some(locales);
+        self
+    }
+
+    pub fn with_poster(mut self, poster: Poster<C>) -> Self {
+        self.poster = Some(poster);
+        self
+    }
+
+    pub fn with_alerter(mut self, alerter: Alerter) -> Self {
+        self.alerter = Some(alerter);
+        self
+    }
+
+    pub fn with_archives(mut self, archives: C) -> Self {
+        self.archives = Some(archives);
+        self
+    }
+
+    /// Check access of the chat and user ids. Admins are always allowed.
+    pub fn is_allowed(&self, ids: &[i64]) -> bool {
+        if ids.iter().any(|id| self.admins.contains(id)) {
+            return true;
+        }
+        self.acl
+            .as_ref()
+            .map(|acl| acl.is_allowed(ids))
+            .unwrap_or(true)
+    }
+
     /// Executed when a command comes in and parsed successfully.
     pub async fn respond_cmd(
         &'static self,
@@ -93,10 +289,11 @@ where
         msg: Message,
         command: Command,
     ) -> ControlFlow<()> {
+        let locale = self.locale(msg.from()).await;
         match command {
             Command::Help => {
                 let _ = bot
-                    .send_message(msg.chat.id, escape(&Command::descriptions()))
+                    .send_message(msg.chat.id, escape(&i18n::help(locale)))
                     .reply_to_message_id(msg.id)
                     .await;
             }
@@ -110,9 +307,11 @@ where
                 let _ = bot
                     .send_message(
                         msg.chat.id,
-                        format!(
-                            "Current chat id is {} \\(in private chat this is your account id\\)",
-                            code_inline(&msg.chat.id.to_string())
+                        tr_md(
+                            locale,
+                            "id-reply",
+                            &[],
+                            &[("id", &code_inline(&msg.chat.id.to_string()))],
                         ),
                     )
                     .reply_to_message_id(msg.id)
@@ -121,48 +320,384 @@ where
             Command::Sync(url) => {
                 if url.is_empty() {
                     let _ = bot
-                        .send_message(msg.chat.id, escape("Usage: /sync url"))
+                        .send_message(msg.chat.id, escape(&tr(locale, "sync-usage", &[])))
                         .reply_to_message_id(msg.id)
                         .await;
                     return ControlFlow::BREAK;
                 }
 
+                self.count_request(msg.chat.id);
+                if self
+                    .reply_limited(&bot, &msg, locale, &url)
+                    .await
+                    .is_break()
+                {
+                    return ControlFlow::BREAK;
+                }
                 info!(
                     "[cmd handler] receive sync request from {:?} for {url}",
                     PrettyChat(&msg.chat)
                 );
+                let user = msg.from().map(|u| u.id);
                 let msg: Message = ok_or_break!(
-                    bot.send_message(msg.chat.id, escape(&format!("Syncing url {url}")))
-                        .reply_to_message_id(msg.id)
-                        .await
+                    bot.send_message(
+                        msg.chat.id,
+                        escape(&tr(locale, "syncing", &[("url", &url)])),
+                    )
+                    .reply_to_message_id(msg.id)
+                    .await
                 );
-                tokio::spawn(async move {
-                    let _ = bot
-                        .edit_message_text(msg.chat.id, msg.id, self.sync_response(&url).await)
-                        .await;
-                });
+                tokio::spawn(self.edit_sync_result(
+                    bot,
+                    message_target(&msg),
+                    url,
+                    false,
+                    user,
+                    locale,
+                ));
+            }
+            Command::Settings(args) => {
+                let text = self.update_settings(locale, &bot, &msg, &args).await;
+                let _ = bot
+                    .send_message(msg.chat.id, escape(&text))
+                    .reply_to_message_id(msg.id)
+                    .await;
+            }
+            Command::Lang(code) => {
+                let text = self.update_locale(locale, &msg, &code).await;
+                let _ = bot
+                    .send_message(msg.chat.id, escape(&text))
+                    .reply_to_message_id(msg.id)
+                    .await;
             }
         };
 
         ControlFlow::BREAK
     }
 
+    /// Show or set the locale of the user. Returns the reply.
+    async fn update_locale(&self, locale: Locale, msg: &Message, code: &str) -> String {
+        let code = code.trim();
+        if code.is_empty() {
+            return format!(
+                "{}\n{}",
+                tr(locale, "lang-current", &[("lang", locale.code())]),
+                tr(locale, "lang-usage", &[])
+            );
+        }
+        let (store, user, new_locale) = match (&self.locales, msg.from(), Locale::from_code(code)) {
+            (Some(store), Some(user), Some(l)) => (store, user, l),
+            _ => return tr(locale, "lang-usage", &[]),
+        };
+        match store.set(user.id, new_locale).await {
+            Ok(_) => tr(new_locale, "lang-set", &[("lang", new_locale.code())]),
+            Err(e) => tr(locale, "lang-failed", &[("error", &e.to_string())]),
+        }
+    }
+
+    /// Show or change settings. Returns the reply.
+    async fn update_settings(
+        &self,
+        locale: Locale,
+        bot: &AutoSend<DefaultParseMode<Bot>>,
+        msg: &Message,
+        args: &str,
+    ) -> String {
+        let store = match &self.settings {
+            Some(s) => s,
+            None => return tr(locale, "settings-disabled", &[]),
+        };
+        let mut settings = match store.get(msg.chat.id).await {
+            Ok(s) => (*s).clone(),
+            Err(e) => return tr(locale, "settings-load-failed", &[("error", &e.to_string())]),
+        };
+        let args = args.trim();
+        if !args.is_empty() {
+            if !self.is_chat_admin(bot, msg).await {
+                return tr(locale, "settings-admin-only", &[]);
+            }
+            let (key, value) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+            if let Err(e) = settings.apply(key, value) {
+                return format!(
+                    "{}\n\n{}",
+                    tr(locale, e, &[]),
+                    tr(locale, "settings-usage", &[])
+                );
+            }
+            if let Err(e) = store.set(msg.chat.id, settings.clone()).await {
+                return tr(locale, "settings-save-failed", &[("error", &e.to_string())]);
+            }
+            info!(
+                "[cmd handler] settings of {:?} changed: {settings:?}",
+                PrettyChat(&msg.chat)
+            );
+        }
+        format!(
+            "{}\n\n{}",
+            settings.describe(
+                locale,
+                default_similarity(&msg.chat),
+                self.preferred_language.as_deref()
+            ),
+            tr(locale, "settings-usage", &[])
+        )
+    }
+
+    /// Bot admins, and admins of the group. Everyone is admin in private chat.
+    async fn is_chat_admin(&self, bot: &AutoSend<DefaultParseMode<Bot>>, msg: &Message) -> bool {
+        if msg.chat.is_private() {
+            return true;
+        }
+        let user = match msg.from() {
+            Some(u) => u.id,
+            None => return false,
+        };
+        if self.admins.contains(&user) {
+            return true;
+        }
+        match bot.get_chat_member(msg.chat.id, user).await {
+            Ok(member) => member.is_privileged(),
+            Err(e) => {
+                tracing::warn!("[cmd handler] unable to get chat member: {e}");
+                false
+            }
+        }
+    }
+
+    /// Locale set by the user, or the language of the user's Telegram client.
+    async fn locale(&self, user: Option<&User>) -> Locale {
+        let user = match user {
+            Some(u) => u,
+            None => return Locale::En,
+        };
+        if let Some(store) = &self.locales {
+            match store.get(user.id).await {
+                Ok(Some(l)) => return l,
+                Ok(None) => (),
+                Err(e) => tracing::error!("[i18n] unable to load locale of {}: {e:?}", user.id),
+            }
+        }
+        user.language_code
+            .as_deref()
+            .and_then(Locale::from_code)
+            .unwrap_or(Locale::En)
+    }
+
+    /// Settings of the chat. Defaults are used if it can not be loaded.
+    async fn chat_settings(&self, chat: i64) -> Arc<ChatSettings> {
+        let store = match &self.settings {
+            Some(s) => s,
+            None => return Default::default(),
+        };
+        match store.get(chat).await {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::error!("[settings] unable to load settings of {chat}: {e:?}");
+                Default::default()
+            }
+        }
+    }
+
     pub async fn respond_admin_cmd(
         &'static self,
         bot: AutoSend<DefaultParseMode<Bot>>,
         msg: Message,
         command: AdminCommand,
     ) -> ControlFlow<()> {
+        let locale = self.locale(msg.from()).await;
         match command {
             AdminCommand::Delete(key) => {
                 let _ = self.synchronizer.delete_cache(&key).await;
                 let _ = bot
-                    .send_message(msg.chat.id, escape(&format!("Key {key} deleted.")))
+                    .send_message(
+                        msg.chat.id,
+                        escape(&tr(locale, "admin-deleted", &[("key", &key)])),
+                    )
+                    .reply_to_message_id(msg.id)
+                    .await;
+            }
+            AdminCommand::CacheGet(url) => {
+                let text = match self.get_cache(&url).await {
+                    Ok(Some(v)) => tr_md(
+                        locale,
+                        "admin-cached",
+                        &[],
+                        &[("page", &link(&v, &escape(&v)))],
+                    ),
+                    Ok(None) => escape(&tr(locale, "admin-not-cached", &[("url", &url)])),
+                    Err(e) => escape(&tr(
+                        locale,
+                        "admin-cache-failed",
+                        &[("error", &e.to_string())],
+                    )),
+                };
+                let _ = bot
+                    .send_message(msg.chat.id, text)
+                    .reply_to_message_id(msg.id)
+                    .await;
+            }
+            AdminCommand::Purge(url) => {
+                let text = match self.purge_cache(&url).await {
+                    Ok(_) => tr(locale, "admin-purged", &[("url", &url)]),
+                    Err(e) => tr(locale, "admin-purge-failed", &[("error", &e.to_string())]),
+                };
+                let _ = bot
+                    .send_message(msg.chat.id, escape(&text))
+                    .reply_to_message_id(msg.id)
+                    .await;
+            }
+            AdminCommand::Resync(url) => {
+                if url.is_empty() {
+                    let _ = bot
+                        .send_message(msg.chat.id, escape(&tr(locale, "admin-resync-usage", &[])))
+                        .reply_to_message_id(msg.id)
+                        .await;
+                    return ControlFlow::BREAK;
+                }
+
+                info!(
+                    "[admin cmd handler] receive resync request from {:?} for {url}",
+                    PrettyChat(&msg.chat)
+                );
+                let user = msg.from().map(|u| u.id);
+                let msg: Message = ok_or_break!(
+                    bot.send_message(
+                        msg.chat.id,
+                        escape(&tr(locale, "resyncing", &[("url", &url)]))
+                    )
+                    .reply_to_message_id(msg.id)
+                    .await
+                );
+                tokio::spawn(self.edit_sync_result(
+                    bot,
+                    message_target(&msg),
+                    url,
+                    true,
+                    user,
+                    locale,
+                ));
+            }
+            AdminCommand::CacheStats => {
+                let stats = self.synchronizer.cache_stats();
+                let total = stats.hits + stats.misses;
+                let rate = if total == 0 {
+                    0.0
+                } else {
+                    stats.hits as f64 * 100.0 / total as f64
+                };
+                let text = tr(
+                    locale,
+                    "admin-cache-stats",
+                    &[
+                        ("hits", &stats.hits.to_string()),
+                        ("misses", &stats.misses.to_string()),
+                        ("rate", &format!("{rate:.2}")),
+                    ],
+                );
+                let _ = bot
+                    .send_message(msg.chat.id, escape(&text))
+                    .reply_to_message_id(msg.id)
+                    .await;
+            }
+            AdminCommand::Stats(period) => {
+                let text = match Period::parse(period.trim()) {
+                    Some(period) => match self.synchronizer.load_stats(period).await {
+                        Ok(counters) => stats_text(locale, period, &counters),
+                        Err(e) => escape(&tr(locale, "stats-failed", &[("error", &e.to_string())])),
+                    },
+                    None => escape(&tr(locale, "stats-usage", &[])),
+                };
+                let _ = bot
+                    .send_message(msg.chat.id, text)
+                    .reply_to_message_id(msg.id)
+                    .await;
+            }
+            AdminCommand::Allow(id) => {
+                self.respond_set_rule(bot, msg, locale, &id, Rule::Allow)
+                    .await;
+            }
+            AdminCommand::Deny(id) => {
+                self.respond_set_rule(bot, msg, locale, &id, Rule::Deny)
+                    .await;
+            }
+            AdminCommand::Unset(id) => {
+                let text = match (&self.acl, id.trim().parse::<i64>()) {
+                    (None, _) => tr(locale, "acl-disabled", &[]),
+                    (_, Err(_)) => tr(locale, "acl-unset-usage", &[]),
+                    (Some(acl), Ok(id)) => match acl.remove(id).await {
+                        Ok(rule) => {
+                            info!("[admin cmd handler] remove acl rule of {id}");
+                            let id = id.to_string();
+                            match rule {
+                                Some(r) => tr(
+                                    locale,
+                                    "acl-removed-config",
+                                    &[("id", &id), ("rule", r.as_str())],
+                                ),
+                                None => tr(locale, "acl-removed", &[("id", &id)]),
+                            }
+                        }
+                        Err(e) => tr(locale, "acl-remove-failed", &[("error", &e.to_string())]),
+                    },
+                };
+                let _ = bot
+                    .send_message(msg.chat.id, escape(&text))
+                    .reply_to_message_id(msg.id)
+                    .await;
+            }
+            AdminCommand::ListAcl => {
+                let text = match &self.acl {
+                    Some(acl) => {
+                        let value = if acl.is_private() {
+                            tr(locale, "settings-on", &[])
+                        } else {
+                            tr(locale, "settings-off", &[])
+                        };
+                        let mut text = tr(locale, "acl-private", &[("value", &value)]);
+                        text.push('\n');
+                        for (id, rule) in acl.list() {
+                            text.push_str(&format!("{} {id}\n", rule.as_str()));
+                        }
+                        text
+                    }
+                    None => tr(locale, "acl-disabled", &[]),
+                };
+                let _ = bot
+                    .send_message(msg.chat.id, escape(&text))
                     .reply_to_message_id(msg.id)
                     .await;
-                ControlFlow::BREAK
             }
         }
+        ControlFlow::BREAK
+    }
+
+    async fn respond_set_rule(
+        &self,
+        bot: AutoSend<DefaultParseMode<Bot>>,
+        msg: Message,
+        locale: Locale,
+        id: &str,
+        rule: Rule,
+    ) {
+        let text = match (&self.acl, id.trim().parse::<i64>()) {
+            (None, _) => tr(locale, "acl-disabled", &[]),
+            (_, Err(_)) => tr(locale, "acl-set-usage", &[]),
+            (Some(acl), Ok(id)) => match acl.set(id, rule).await {
+                Ok(_) => {
+                    info!("[admin cmd handler] set acl rule of {id} to {rule:?}");
+                    tr(
+                        locale,
+                        "acl-set",
+                        &[("id", &id.to_string()), ("rule", rule.as_str())],
+                    )
+                }
+                Err(e) => tr(locale, "acl-set-failed", &[("error", &e.to_string())]),
+            },
+        };
+        let _ = bot
+            .send_message(msg.chat.id, escape(&text))
+            .reply_to_message_id(msg.id)
+            .await;
     }
 
     pub async fn respond_text(
@@ -170,151 +705,217 @@ where
         bot: AutoSend<DefaultParseMode<Bot>>,
         msg: Message,
     ) -> ControlFlow<()> {
-        let maybe_link = {
-            let entries = msg
-                .entities()
-                .map(|es| {
-                    es.iter().filter_map(|e| {
-                        if let teloxide::types::MessageEntityKind::TextLink { url } = &e.kind {
-                            Synchronizer::match_url_from_text(url.as_ref()).map(ToOwned::to_owned)
-                        } else {
-                            None
-                        }
-                    })
-                })
-                .into_iter()
-                .flatten();
-            msg.text()
-                .and_then(|content| {
-                    Synchronizer::match_url_from_text(content).map(ToOwned::to_owned)
-                })
-                .into_iter()
-                .chain(entries)
-                .next()
-        };
+        if !self.chat_settings(msg.chat.id).await.link_detection {
+            return ControlFlow::CONTINUE;
+        }
+        let urls = extract_urls(msg.text(), msg.entities());
+        if urls.is_empty() {
+            // fallback to the next branch
+            return ControlFlow::CONTINUE;
+        }
+        self.sync_urls(bot, msg, urls, "text handler").await
+    }
+
+    pub async fn respond_caption(
+        &'static self,
+        bot: AutoSend<DefaultParseMode<Bot>>,
+        msg: Message,
+    ) -> ControlFlow<()> {
+        if !self.chat_settings(msg.chat.id).await.link_detection {
+            return ControlFlow::CONTINUE;
+        }
+        let urls = extract_urls(msg.caption(), msg.caption_entities());
+        if urls.is_empty() {
+            return ControlFlow::CONTINUE;
+        }
+        self.sync_urls(bot, msg, urls, "caption handler").await
+    }
 
-        if let Some(url) = maybe_link {
+    /// Sync urls found in the message.
+    /// Multiple urls are synced as a batch with a combined status message.
+    async fn sync_urls(
+        &'static self,
+        bot: AutoSend<DefaultParseMode<Bot>>,
+        msg: Message,
+        mut urls: Vec<String>,
+        tag: &str,
+    ) -> ControlFlow<()> {
+        let locale = self.locale(msg.from()).await;
+        let user = msg.from().map(|u| u.id);
+        if urls.len() == 1 {
+            let url = urls.remove(0);
+            self.count_request(msg.chat.id);
+            if self
+                .reply_limited(&bot, &msg, locale, &url)
+                .await
+                .is_break()
+            {
+                return ControlFlow::BREAK;
+            }
             info!(
-                "[text handler] receive sync request from {:?} for {url}",
+                "[{tag}] receive sync request from {:?} for {url}",
                 PrettyChat(&msg.chat)
             );
             let msg: Message = ok_or_break!(
-                bot.send_message(msg.chat.id, escape(&format!("Syncing url {url}")))
-                    .reply_to_message_id(msg.id)
-                    .await
+                bot.send_message(
+                    msg.chat.id,
+                    escape(&tr(locale, "syncing", &[("url", &url)])),
+                )
+                .reply_to_message_id(msg.id)
+                .await
             );
-            tokio::spawn(async move {
-                let _ = bot
-                    .edit_message_text(msg.chat.id, msg.id, self.sync_response(&url).await)
-                    .await;
-            });
+            tokio::spawn(self.edit_sync_result(
+                bot,
+                message_target(&msg),
+                url,
+                false,
+                user,
+                locale,
+            ));
             return ControlFlow::BREAK;
         }
 
-        // fallback to the next branch
-        ControlFlow::CONTINUE
+        let truncated = urls.len() > MAX_BATCH_SIZE;
+        urls.truncate(MAX_BATCH_SIZE);
+        info!(
+            "[{tag}] receive batch sync request from {:?} for {} urls",
+            PrettyChat(&msg.chat),
+            urls.len()
+        );
+        let mut batch = Batch {
+            locale,
+            truncated,
+            items: Vec::with_capacity(urls.len()),
+        };
+        for url in urls {
+            self.count_request(msg.chat.id);
+            // limited urls are failed directly
+            let state = match self.limited(locale, user, msg.chat.id, &url).await {
+                Some(text) => BatchState::Failed(text),
+                None => BatchState::Pending,
+            };
+            batch.items.push((url, state));
+        }
+        let msg: Message = ok_or_break!(
+            bot.send_message(msg.chat.id, batch.text())
+                .reply_to_message_id(msg.id)
+                .disable_web_page_preview(true)
+                .await
+        );
+        tokio::spawn(self.edit_batch_result(bot, msg, batch, user));
+        ControlFlow::BREAK
     }
 
-    pub async fn respond_caption(
+    pub async fn respond_photo(
         &'static self,
         bot: AutoSend<DefaultParseMode<Bot>>,
         msg: Message,
     ) -> ControlFlow<()> {
-        let caption_entities = msg.caption_entities();
-        let mut final_url = None;
-        for entry in caption_entities.map(|x| x.iter()).into_iter().flatten() {
-            let url = match &entry.kind {
-                teloxide::types::MessageEntityKind::Url => {
-                    let raw = msg
-                        .caption()
-                        .expect("Url MessageEntry found but caption is None");
-                    let encoded: Vec<_> = raw
-                        .encode_utf16()
-                        .into_iter()
-                        .skip(entry.offset)
-                        .take(entry.length)
-                        .collect();
-                    let content = ok_or_break!(String::from_utf16(&encoded));
-                    Cow::from(content)
-                }
-                teloxide::types::MessageEntityKind::TextLink { url } => Cow::from(url.as_ref()),
-                _ => {
-                    continue;
-                }
-            };
-            let url = if let Some(c) = Synchronizer::match_url_from_url(&url) {
-                c
-            } else {
-                continue;
-            };
-            final_url = Some(url.to_string());
-            break;
+        let settings = self.chat_settings(msg.chat.id).await;
+        if !settings.photo_search || image_file_id(&msg).is_none() {
+            return ControlFlow::CONTINUE;
         }
 
-        match final_url {
-            Some(url) => {
-                info!(
-                    "[caption handler] receive sync request from {:?} for {url}",
-                    PrettyChat(&msg.chat)
-                );
-                let msg: Message = ok_or_break!(
-                    bot.send_message(msg.chat.id, escape(&format!("Syncing url {url}")))
-                        .reply_to_message_id(msg.id)
-                        .await
-                );
-                let url = url.to_string();
+        // images of an album come as separate messages, so collect them first
+        if let Some(group) = msg.media_group_id() {
+            let group = group.to_string();
+            let first = {
+                let mut groups = self.media_groups.lock();
+                let msgs = groups.entry(group.clone()).or_default();
+                msgs.push(msg);
+                msgs.len() == 1
+            };
+            if first {
                 tokio::spawn(async move {
-                    let _ = bot
-                        .edit_message_text(msg.chat.id, msg.id, self.sync_response(&url).await)
-                        .await;
+                    tokio::time::sleep(MEDIA_GROUP_WAIT).await;
+                    let msgs = self.media_groups.lock().remove(&group);
+                    if let Some(msgs) = msgs {
+                        let _ = self.respond_images(bot, msgs, settings).await;
+                    }
                 });
-                ControlFlow::BREAK
             }
-            None => ControlFlow::CONTINUE,
+            return ControlFlow::BREAK;
         }
+        self.respond_images(bot, vec![msg], settings).await
     }
 
-    pub async fn respond_photo(
+    /// Search images and sync the gallery most of them match.
+    /// Replies to the first message.
+    async fn respond_images(
         &'static self,
         bot: AutoSend<DefaultParseMode<Bot>>,
-        msg: Message,
+        msgs: Vec<Message>,
+        settings: Arc<ChatSettings>,
     ) -> ControlFlow<()> {
-        let first_photo = match msg.photo().and_then(|x| x.first()) {
-            Some(p) => p,
-            None => {
-                return ControlFlow::CONTINUE;
-            }
+        let msg = match msgs.first() {
+            Some(m) => m,
+            None => return ControlFlow::BREAK,
         };
+        let locale = self.locale(msg.from()).await;
+        let threshold = settings
+            .min_similarity
+            .unwrap_or_else(|| default_similarity(&msg.chat));
+        let language = settings
+            .preferred_language
+            .as_deref()
+            .or(self.preferred_language.as_deref());
 
-        let f = ok_or_break!(bot.get_file(&first_photo.file_id).await);
-        let mut buf: Vec<u8> = Vec::with_capacity(f.file_size as usize);
-        ok_or_break!(teloxide::net::Download::download_file(&bot, &f.file_path, &mut buf).await);
-        let search_result: SaucenaoOutput = ok_or_break!(self.searcher.search(buf).await);
-
-        let mut url_sim = None;
-        let threshold = if msg.chat.is_private() {
-            MIN_SIMILARITY_PRIVATE
-        } else {
-            MIN_SIMILARITY
-        };
-        for element in search_result
-            .data
-            .into_iter()
-            .filter(|x| x.similarity >= threshold)
+        let mut matches = Vec::new();
+        let mut source = None;
+        for file_id in msgs
+            .iter()
+            .filter_map(image_file_id)
+            .take(MAX_MEDIA_GROUP_SEARCH)
         {
-            match element.parsed {
-                SaucenaoParsed::EHentai(f_hash) => {
-                    url_sim = Some((
-                        ok_or_break!(self.convertor.convert_to_gallery(&f_hash).await),
-                        element.similarity,
-                    ));
-                    break;
+            match self.search_image(&bot, file_id, threshold, language).await {
+                Ok(ImageMatch::Gallery(url, score)) => matches.push((url, score)),
+                Ok(ImageMatch::Source(hit)) => {
+                    if source.is_none() {
+                        source = Some(hit);
+                    }
                 }
-                SaucenaoParsed::NHentai(nid) => {
-                    url_sim = Some((format!("https://nhentai.net/g/{nid}/"), element.similarity));
-                    break;
+                Ok(ImageMatch::NotFound) => (),
+                Err(e) => {
+                    tracing::error!("[photo handler] search image failed: {e:?}");
+                    self.alert(Category::of_search(&e), "Search image failed", &e);
                 }
-                _ => continue,
+            }
+        }
+        if matches.len() > 1 {
+            info!("[photo handler] matches of media group: {matches:?}");
+        }
+        let url_sim = vote(&matches);
+
+        if url_sim.is_none() && msg.chat.is_private() {
+            if let Some(hit) = source {
+                info!(
+                    "[photo handler] found unsyncable source {} from {} with score {}",
+                    hit.url, hit.engine, hit.score
+                );
+                ok_or_break!(
+                    bot.send_message(
+                        msg.chat.id,
+                        tr_md(
+                            locale,
+                            "source-found",
+                            &[("engine", hit.engine)],
+                            &[(
+                                "source",
+                                &link(
+                                    &hit.url,
+                                    &escape(if hit.name.is_empty() {
+                                        &hit.url
+                                    } else {
+                                        &hit.name
+                                    })
+                                )
+                            )]
+                        )
+                    )
+                    .reply_to_message_id(msg.id)
+                    .await
+                );
+                return ControlFlow::BREAK;
             }
         }
 
@@ -326,34 +927,236 @@ where
             }
         };
 
+        if self.reply_limited(&bot, msg, locale, &url).await.is_break() {
+            return ControlFlow::BREAK;
+        }
         info!(
             "[photo handler] receive sync request from {:?} for {url} with similarity {sim}",
             PrettyChat(&msg.chat)
         );
 
+        let user = msg.from().map(|u| u.id);
         if let Ok(msg) = bot
-            .send_message(msg.chat.id, escape(&format!("Syncing url {url}")))
+            .send_message(
+                msg.chat.id,
+                escape(&tr(locale, "syncing", &[("url", &url)])),
+            )
             .reply_to_message_id(msg.id)
             .await
         {
-            tokio::spawn(async move {
-                let _ = bot
-                    .edit_message_text(msg.chat.id, msg.id, self.sync_response(&url).await)
-                    .await;
-            });
+            tokio::spawn(self.edit_sync_result(
+                bot,
+                message_target(&msg),
+                url,
+                false,
+                user,
+                locale,
+            ));
+        }
+
+        ControlFlow::BREAK
+    }
+
+    pub async fn respond_inline(
+        &'static self,
+        bot: AutoSend<DefaultParseMode<Bot>>,
+        query: InlineQuery,
+    ) -> ControlFlow<()> {
+        let text = query.query.trim();
+        if text.is_empty() {
+            return ControlFlow::BREAK;
         }
+        let locale = self.locale(Some(&query.from)).await;
 
+        let mut cache_time = None;
+        let results: Vec<InlineQueryResult> = match Synchronizer::match_url_from_text(text) {
+            Some(url) => {
+                let url = url.to_string();
+                match ok_or_break!(self.get_record(&url).await) {
+                    Some(mut record) => {
+                        if record.link.is_empty() {
+                            record.link = url;
+                        }
+                        vec![inline_article(0, &record)]
+                    }
+                    None => {
+                        if let Some(text) = self
+                            .limited(locale, Some(query.from.id), query.from.id, &url)
+                            .await
+                        {
+                            let article = InlineQueryResultArticle::new(
+                                "0",
+                                text,
+                                InputMessageContent::Text(InputMessageContentText::new(&url)),
+                            );
+                            cache_time = Some(0);
+                            vec![InlineQueryResult::Article(article)]
+                        } else {
+                            info!(
+                                "[inline handler] receive sync request from {} for {url}",
+                                query.from.id
+                            );
+                            // the result will be ready when user queries again
+                            cache_time = Some(0);
+                            let article = InlineQueryResultArticle::new(
+                                "0",
+                                tr(locale, "inline-syncing", &[]),
+                                InputMessageContent::Text(InputMessageContentText::new(&url)),
+                            )
+                            .description(&url);
+                            tokio::spawn(async move {
+                                let _ = self.sync_result(&url, false, Some(query.from.id)).await;
+                            });
+                            vec![InlineQueryResult::Article(article)]
+                        }
+                    }
+                }
+            }
+            None => self
+                .synchronizer
+                .search_records(text, INLINE_RESULT_LIMIT)
+                .iter()
+                .enumerate()
+                .map(|(idx, record)| inline_article(idx, record))
+                .collect(),
+        };
+
+        let mut answer = bot.answer_inline_query(query.id, results);
+        if let Some(t) = cache_time {
+            answer = answer.cache_time(t);
+        }
+        ok_or_break!(answer.await);
         ControlFlow::BREAK
     }
 
+    pub async fn respond_callback(
+        &'static self,
+        bot: AutoSend<DefaultParseMode<Bot>>,
+        query: CallbackQuery,
+    ) -> ControlFlow<()> {
+        let (action, url) = match query.data.as_deref().and_then(Action::decode) {
+            Some((action, url)) => (action, url.to_string()),
+            None => return ControlFlow::CONTINUE,
+        };
+        info!(
+            "[callback handler] receive {} request from {} for {url}",
+            action.name(),
+            query.from.id
+        );
+
+        let locale = self.locale(Some(&query.from)).await;
+        let is_admin = self.admins.contains(&query.from.id);
+        // inline messages have no chat, use the user instead
+        let chat = query
+            .message
+            .as_ref()
+            .map(|m| m.chat.id)
+            .unwrap_or(query.from.id);
+        let answer = match action {
+            Action::Resync | Action::Delete if !is_admin => tr(locale, "callback-admin-only", &[]),
+            // exporting is expensive, so only admins can do it without rate limits
+            Action::Archive if self.limiter.is_none() && !is_admin => {
+                tr(locale, "callback-admin-only", &[])
+            }
+            Action::Resync => {
+                // messages sent in inline mode are edited by inline message id
+                let target = match (query.message, query.inline_message_id) {
+                    (Some(msg), _) => Some(message_target(&msg)),
+                    (None, Some(id)) => Some(TargetMessage::Inline {
+                        inline_message_id: id,
+                    }),
+                    (None, None) => None,
+                };
+                match target {
+                    Some(target) => {
+                        let text = escape(&tr(locale, "resyncing", &[("url", &url)]));
+                        ok_or_break!(edit_target(&bot, &target, text, None).await);
+                        tokio::spawn(self.edit_sync_result(
+                            bot.clone(),
+                            target,
+                            url,
+                            true,
+                            Some(query.from.id),
+                            locale,
+                        ));
+                        tr(locale, "callback-resyncing", &[])
+                    }
+                    None => tr(locale, "callback-message-missing", &[]),
+                }
+            }
+            Action::Delete => match self.purge_cache(&url).await {
+                Ok(_) => tr(locale, "callback-deleted", &[]),
+                Err(e) => tr(
+                    locale,
+                    "callback-delete-failed",
+                    &[("error", &e.to_string())],
+                ),
+            },
+            // reports do not count towards sync quota, they are deduped by `REPORT_WINDOW`
+            Action::Report => {
+                self.report_broken(&bot, &query.from, &url).await;
+                tr(locale, "callback-reported", &[])
+            }
+            Action::Archive => match self.acquire(locale, Some(query.from.id), chat).await {
+                Some(reply) => reply,
+                None => {
+                    tokio::spawn(self.send_archive(bot.clone(), chat, url, locale));
+                    tr(locale, "callback-archiving", &[])
+                }
+            },
+        };
+        let _ = bot.answer_callback_query(query.id).text(answer).await;
+        ControlFlow::BREAK
+    }
+
+    /// Notify admins of the broken gallery.
+    /// Reports of the same gallery within `REPORT_WINDOW` are dropped.
+    async fn report_broken(&self, bot: &AutoSend<DefaultParseMode<Bot>>, user: &User, url: &str) {
+        if !self.first_report(url) {
+            info!("[callback handler] {url} is reported already");
+            return;
+        }
+        let cached = self.get_cache(url).await.ok().flatten();
+        for admin in self.admins.iter() {
+            // only the language set with /lang is known here
+            let locale = match &self.locales {
+                Some(store) => store.get(*admin).await.ok().flatten(),
+                None => None,
+            }
+            .unwrap_or(Locale::En);
+            let page = cached
+                .clone()
+                .unwrap_or_else(|| tr(locale, "admin-report-not-cached", &[]));
+            let text = tr_md(
+                locale,
+                "admin-report",
+                &[("user", &user.full_name()), ("url", url), ("page", &page)],
+                &[("id", &code_inline(&user.id.to_string()))],
+            );
+            let _ = bot.send_message(*admin, text).await;
+        }
+    }
+
+    /// Record the report and return whether it is the first one in the window.
+    fn first_report(&self, url: &str) -> bool {
+        let mut reported = self.reported.lock();
+        reported.retain(|_, t| t.elapsed() < REPORT_WINDOW);
+        if reported.contains_key(url) {
+            return false;
+        }
+        reported.insert(url.to_string(), Instant::now());
+        true
+    }
+
     pub async fn respond_default(
         &'static self,
         bot: AutoSend<DefaultParseMode<Bot>>,
         msg: Message,
     ) -> ControlFlow<()> {
         if msg.chat.is_private() {
+            let locale = self.locale(msg.from()).await;
             ok_or_break!(
-                bot.send_message(msg.chat.id, escape("Unrecognized message."))
+                bot.send_message(msg.chat.id, escape(&tr(locale, "unrecognized", &[])))
                     .reply_to_message_id(msg.id)
                     .await
             );
@@ -363,51 +1166,819 @@ where
         ControlFlow::BREAK
     }
 
-    async fn sync_response(&self, url: &str) -> String {
-        self.single_flight
-            .work(url, || async {
-                match self.route_sync(url).await {
-                    Ok(url) => {
-                        format!("Sync to telegraph finished: {}", link(&url, &escape(&url)))
+    fn count_request(&self, chat: i64) {
+        if let Some(stats) = self.synchronizer.stats() {
+            stats.incr(format!("chat|{chat}"), 1);
+        }
+    }
+
+    /// Check rate limits and quotas before syncing the url.
+    /// Returns the reply if it is limited. Admins and cached urls are exempt.
+    async fn limited(
+        &self,
+        locale: Locale,
+        user: Option<i64>,
+        chat: i64,
+        url: &str,
+    ) -> Option<String> {
+        self.limiter.as_ref()?;
+        if let Ok(Some(_)) = self.get_cache(url).await {
+            return None;
+        }
+        self.acquire(locale, user, chat).await
+    }
+
+    /// Reply to the message and break if syncing the url is limited.
+    async fn reply_limited(
+        &self,
+        bot: &AutoSend<DefaultParseMode<Bot>>,
+        msg: &Message,
+        locale: Locale,
+        url: &str,
+    ) -> ControlFlow<()> {
+        let user = msg.from().map(|u| u.id);
+        match self.limited(locale, user, msg.chat.id, url).await {
+            Some(text) => {
+                let _ = bot
+                    .send_message(msg.chat.id, escape(&text))
+                    .reply_to_message_id(msg.id)
+                    .await;
+                ControlFlow::BREAK
+            }
+            None => ControlFlow::CONTINUE,
+        }
+    }
+
+    /// Take a quota of the user and chat. Returns the reply if it is limited.
+    /// Admins are exempt.
+    async fn acquire(&self, locale: Locale, user: Option<i64>, chat: i64) -> Option<String> {
+        let limiter = self.limiter.as_ref()?;
+        if self.admins.contains(&chat) || user.map(|u| self.admins.contains(&u)) == Some(true) {
+            return None;
+        }
+        match limiter.acquire(user, chat).await {
+            Ok(None) => None,
+            Ok(Some(wait)) => {
+                info!("[limiter] user {user:?} in chat {chat} is limited for {wait:?}");
+                Some(tr(locale, "limited", &[("wait", &format_wait(wait))]))
+            }
+            Err(e) => {
+                // do not block users when the storage is unavailable
+                tracing::error!("[limiter] unable to check quota: {e:?}");
+                None
+            }
+        }
+    }
+
+    /// Search the image and resolve the first syncable hit above threshold.
+    async fn search_image(
+        &self,
+        bot: &AutoSend<DefaultParseMode<Bot>>,
+        file_id: &str,
+        threshold: u8,
+        language: Option<&str>,
+    ) -> anyhow::Result<ImageMatch> {
+        let f = bot.get_file(file_id).await?;
+        let mut buf: Vec<u8> = Vec::with_capacity(f.file_size as usize);
+        teloxide::net::Download::download_file(bot, &f.file_path, &mut buf).await?;
+        let hits: Vec<SearchHit> = self.searcher.search(buf).await?;
+
+        let mut source = None;
+        for hit in hits.into_iter().filter(|x| x.score >= threshold) {
+            match self.resolver.resolve(&hit, language).await {
+                Ok(Some(resolved)) => return Ok(ImageMatch::Gallery(resolved.url, hit.score)),
+                // the source can not be synced, but it is still useful
+                Ok(None) => {
+                    if source.is_none() {
+                        source = Some(hit);
                     }
-                    Err(e) => {
-                        format!("Sync to telegraph failed: {}", escape(&e.to_string()))
+                }
+                // later hits may still be resolved
+                Err(e) => {
+                    tracing::warn!("[search] unable to resolve {}: {e:?}", hit.url);
+                    if source.is_none() {
+                        source = Some(hit);
                     }
                 }
+            }
+        }
+        Ok(source
+            .map(ImageMatch::Source)
+            .unwrap_or(ImageMatch::NotFound))
+    }
+
+    /// Sync pending urls of the batch, and update the status message when
+    /// each of them finishes.
+    async fn edit_batch_result(
+        &'static self,
+        bot: AutoSend<DefaultParseMode<Bot>>,
+        msg: Message,
+        mut batch: Batch,
+        user: Option<i64>,
+    ) {
+        let mut results = futures::stream::iter(
+            batch
+                .items
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, state))| matches!(state, BatchState::Pending))
+                .map(|(idx, (url, _))| (idx, url.clone()))
+                .collect::<Vec<_>>(),
+        )
+        .map(|(idx, url)| async move { (idx, self.sync_result(&url, false, user).await) })
+        .buffer_unordered(BATCH_CONCURRENCY);
+
+        while let Some((idx, result)) = results.next().await {
+            batch.items[idx].1 = match result {
+                Ok(page) => BatchState::Done(page),
+                Err(e) => BatchState::Failed(e),
+            };
+            let _ = bot
+                .edit_message_text(msg.chat.id, msg.id, batch.text())
+                .disable_web_page_preview(true)
+                .await;
+        }
+    }
+
+    /// Sync and edit the message with the result.
+    /// Action buttons are attached when succeeded.
+    async fn edit_sync_result(
+        &'static self,
+        bot: AutoSend<DefaultParseMode<Bot>>,
+        target: TargetMessage,
+        url: String,
+        force: bool,
+        user: Option<i64>,
+        locale: Locale,
+    ) {
+        let action = if force { "resync" } else { "sync" };
+        let result = self.sync_result(&url, force, user).await;
+        let text = match &result {
+            Ok(page) => tr_md(
+                locale,
+                &format!("{action}-finished"),
+                &[],
+                &[("page", &link(page, &escape(page)))],
+            ),
+            Err(e) => escape(&tr(locale, &format!("{action}-failed"), &[("error", e)])),
+        };
+        let keyboard = result.is_ok().then(|| result_keyboard(locale, &url));
+        let _ = edit_target(&bot, &target, text, keyboard).await;
+    }
+
+    /// Sync the url requested by the user.
+    /// Galleries synced by trusted users are posted to the channel.
+    async fn sync_result(
+        &'static self,
+        url: &str,
+        force: bool,
+        user: Option<i64>,
+    ) -> Result<String, String> {
+        self.sync_with_progress(url, force, user, None).await
+    }
+
+    /// Same as `sync_result`, and the upload progress is reported if this call starts the sync.
+    /// Syncs from the bot and the api share the same flight.
+    pub(crate) async fn sync_with_progress(
+        &'static self,
+        url: &str,
+        force: bool,
+        user: Option<i64>,
+        progress: Option<Arc<Progress>>,
+    ) -> Result<String, String> {
+        let key = if force {
+            format!("resync|{url}")
+        } else {
+            url.to_string()
+        };
+        let synced = self
+            .single_flight
+            .work(&key, || async {
+                let r = match progress {
+                    Some(p) => {
+                        self.synchronizer
+                            .sync_url_with_progress(url, force, p)
+                            .await
+                    }
+                    None => self.route_sync(url, force).await,
+                };
+                r.map_err(|e| {
+                    self.alert(Category::of_sync(&e), &format!("Sync {url} failed"), &e);
+                    e.to_string()
+                })
             })
-            .await
+            .await?;
+        let url = synced.url.clone();
+        self.spawn_post(synced, user);
+        Ok(url)
+    }
+
+    /// Post the gallery in background if the user is trusted.
+    /// Every user waiting for the same sync is checked, not only the one who started it.
+    fn spawn_post(&'static self, synced: Synced, user: Option<i64>) {
+        let poster = match &self.poster {
+            Some(p) => p,
+            None => return,
+        };
+        let trusted = user
+            .map(|u| self.admins.contains(&u) || poster.is_trusted(u))
+            .unwrap_or_default();
+        if !trusted {
+            return;
+        }
+        tokio::spawn(async move {
+            if let Err(e) = poster.post(&synced).await {
+                tracing::error!("[poster] unable to post {}: {e:?}", synced.url);
+            }
+        });
     }
 
-    async fn route_sync(&self, url: &str) -> anyhow::Result<String> {
-        let u = Url::parse(url).map_err(|_| anyhow::anyhow!("Invalid url"))?;
-        let host = u.host_str().unwrap_or_default();
-        let path = u.path().to_string();
+    fn alert(&self, category: Option<Category>, context: &str, err: &anyhow::Error) {
+        if let (Some(alerter), Some(category)) = (&self.alerter, category) {
+            alerter.report(category, context, err);
+        }
+    }
 
-        // TODO: use macro to generate them
-        #[allow(clippy::single_match)]
-        match host {
-            "e-hentai.org" => {
-                info!("[registry] sync e-hentai for path {}", path);
-                self.synchronizer
-                    .sync::<EHCollector>(path)
-                    .await
-                    .map_err(anyhow::Error::from)
+    /// Export the gallery as a CBZ archive and send it to the chat.
+    async fn send_archive(
+        &'static self,
+        bot: AutoSend<DefaultParseMode<Bot>>,
+        chat: i64,
+        url: String,
+        locale: Locale,
+    ) {
+        let path =
+            std::env::temp_dir().join(format!("eh2telegraph-{:016x}.cbz", rand::random::<u64>()));
+        let r = self.export_archive(&bot, chat, &url, &path).await;
+        if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::error!("[archive] unable to remove {path:?}: {e}");
             }
-            "nhentai.to" | "nhentai.net" => {
-                info!("[registry] sync nhentai for path {}", path);
-                self.synchronizer
-                    .sync::<NHCollector>(path)
-                    .await
-                    .map_err(anyhow::Error::from)
+        }
+        let e = match r {
+            Ok(_) => return,
+            Err(e) => e,
+        };
+        let text = match e.downcast_ref::<ArchiveError>() {
+            Some(ArchiveError::TooLarge(_)) => tr(locale, "archive-too-large", &[]),
+            _ => {
+                self.alert(Category::of_sync(&e), &format!("Archive {url} failed"), &e);
+                tr(locale, "archive-failed", &[("error", &e.to_string())])
             }
-            "exhentai.org" => {
-                info!("[registry] sync exhentai for path {}", path);
-                self.synchronizer
-                    .sync::<EXCollector>(path)
+        };
+        let _ = bot.send_message(chat, escape(&text)).await;
+    }
+
+    async fn export_archive(
+        &self,
+        bot: &AutoSend<DefaultParseMode<Bot>>,
+        chat: i64,
+        url: &str,
+        path: &std::path::Path,
+    ) -> anyhow::Result<()> {
+        let key = format!("{ARCHIVE_KEY_PREFIX}{url}");
+        if let Some(archives) = &self.archives {
+            if let Some(file_id) = archives.get(&key).await.ok().flatten() {
+                info!("[archive] send cached {url} for {chat}");
+                match bot
+                    .send_document(chat, InputFile::file_id(file_id))
+                    .caption(escape(url))
                     .await
-                    .map_err(anyhow::Error::from)
+                {
+                    Ok(_) => return Ok(()),
+                    // the file may be unavailable, export it again
+                    Err(e) => tracing::warn!("[archive] unable to send cached {url}: {e}"),
+                }
+            }
+        }
+
+        info!("[archive] export {url} for {chat}");
+        let file = std::fs::File::create(path)?;
+        let writer = CbzWriter::new(file).with_limit(MAX_ARCHIVE_SIZE);
+        let (meta, _) = self.synchronizer.export_url(url, writer).await?;
+        let document = InputFile::file(path).file_name(archive_name(&meta.name));
+        let sent = bot
+            .send_document(chat, document)
+            .caption(escape(url))
+            .await?;
+        if let (Some(archives), Some(document)) = (&self.archives, sent.document()) {
+            if let Err(e) = archives.set(key, document.file_id.clone(), None).await {
+                tracing::error!("[archive] unable to save file id of {url}: {e:?}");
+            }
+        }
+        Ok(())
+    }
+
+    async fn route_sync(&self, url: &str, force: bool) -> anyhow::Result<Synced> {
+        self.synchronizer.sync_url(url, force).await
+    }
+
+    async fn get_record(&self, url: &str) -> anyhow::Result<Option<CacheRecord>> {
+        self.synchronizer.get_record_url(url).await
+    }
+
+    async fn get_cache(&self, url: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.get_record(url).await?.map(|r| r.url))
+    }
+
+    async fn purge_cache(&self, url: &str) -> anyhow::Result<()> {
+        self.synchronizer.purge_url(url).await?;
+        if let Some(archives) = &self.archives {
+            archives
+                .delete(&format!("{ARCHIVE_KEY_PREFIX}{url}"))
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// File id of the image in the message for searching.
+/// The largest photo size, image documents, static stickers, and thumbnails
+/// of animations and video stickers are supported.
+/// Documents keep the original bytes, so they can be found by file hash.
+pub fn image_file_id(msg: &Message) -> Option<&str> {
+    if let Some(photo) = msg.photo() {
+        return photo.last().map(|p| p.file_id.as_str());
+    }
+    // animations are documents too, so they are checked first
+    if let Some(animation) = msg.animation() {
+        return animation.thumb.as_ref().map(|t| t.file_id.as_str());
+    }
+    if let Some(sticker) = msg.sticker() {
+        return if sticker.is_animated || sticker.is_video {
+            sticker.thumb.as_ref().map(|t| t.file_id.as_str())
+        } else {
+            Some(&sticker.file_id)
+        };
+    }
+    msg.document()
+        .filter(|d| {
+            d.mime_type
+                .as_ref()
+                .map(|m| m.type_().as_str() == "image")
+                .unwrap_or_default()
+                && d.file_size.unwrap_or_default() <= MAX_IMAGE_DOCUMENT_SIZE
+        })
+        .map(|d| d.file_id.as_str())
+}
+
+/// The gallery most images match and its average score.
+/// Galleries with the same votes are ranked by total score, then the first matched wins.
+fn vote(matches: &[(String, u8)]) -> Option<(String, u32)> {
+    // (url, votes, total score) in the order of the first match
+    let mut votes: Vec<(&str, usize, u32)> = Vec::new();
+    for (url, score) in matches {
+        match votes.iter_mut().find(|v| v.0 == url) {
+            Some(v) => {
+                v.1 += 1;
+                v.2 += *score as u32;
+            }
+            None => votes.push((url, 1, *score as u32)),
+        }
+    }
+    // max_by_key returns the last max, so iterate reversed to keep the first one
+    votes
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count, score)| (*count, *score))
+        .map(|(url, count, score)| (url.to_string(), score / count as u32))
+}
+
+enum ImageMatch {
+    /// Syncable gallery url and its score.
+    Gallery(String, u8),
+    /// The best hit which can not be synced.
+    Source(SearchHit),
+    NotFound,
+}
+
+/// All distinct gallery urls in the text and its links.
+/// Url entities are not checked since their urls are in the text already.
+fn extract_urls(text: Option<&str>, entities: Option<&[MessageEntity]>) -> Vec<String> {
+    let text_links = entities
+        .into_iter()
+        .flatten()
+        .filter_map(|e| match &e.kind {
+            MessageEntityKind::TextLink { url } => Synchronizer::match_url_from_text(url.as_ref()),
+            _ => None,
+        });
+    let mut urls: Vec<String> = Vec::new();
+    for url in text
+        .map(Synchronizer::match_urls_from_text)
+        .unwrap_or_default()
+        .into_iter()
+        .chain(text_links)
+    {
+        if !urls.iter().any(|u| u == url) {
+            urls.push(url.to_string());
+        }
+    }
+    urls
+}
+
+enum BatchState {
+    Pending,
+    Done(String),
+    Failed(String),
+}
+
+/// Urls synced together and their states.
+struct Batch {
+    locale: Locale,
+    /// Some urls are dropped since there are too many of them.
+    truncated: bool,
+    items: Vec<(String, BatchState)>,
+}
+
+impl Batch {
+    /// Status message in MarkdownV2.
+    fn text(&self) -> String {
+        let (mut done, mut failed) = (0, 0);
+        let mut lines = Vec::with_capacity(self.items.len());
+        for (url, state) in self.items.iter() {
+            lines.push(match state {
+                BatchState::Pending => format!("⏳ {}", escape(url)),
+                BatchState::Done(page) => {
+                    done += 1;
+                    format!("✅ {}", link(page, &escape(url)))
+                }
+                BatchState::Failed(e) => {
+                    failed += 1;
+                    format!(
+                        "❌ {}: {}",
+                        escape(url),
+                        escape(&truncate(e, MAX_BATCH_ERROR_LEN))
+                    )
+                }
+            });
+        }
+
+        let total = self.items.len();
+        let header = if done + failed == total {
+            tr(
+                self.locale,
+                "batch-finished",
+                &[
+                    ("succeeded", &done.to_string()),
+                    ("failed", &failed.to_string()),
+                ],
+            )
+        } else {
+            tr(
+                self.locale,
+                "batch-syncing",
+                &[
+                    ("done", &(done + failed).to_string()),
+                    ("total", &total.to_string()),
+                ],
+            )
+        };
+        let mut text = escape(&header);
+        if self.truncated {
+            text.push('\n');
+            text.push_str(&escape(&tr(
+                self.locale,
+                "batch-truncated",
+                &[("max", &MAX_BATCH_SIZE.to_string())],
+            )));
+        }
+        text.push_str("\n\n");
+        text.push_str(&lines.join("\n"));
+        text
+    }
+}
+
+/// Usage statistics in MarkdownV2.
+fn stats_text(locale: Locale, period: Period, counters: &Counters) -> String {
+    let get = |k: &str| counters.get(k).copied().unwrap_or_default();
+    let list = |prefix: &str, limit: usize| {
+        let items: Vec<_> = group(counters, prefix)
+            .into_iter()
+            .take(limit)
+            .map(|(k, v)| format!("{k} {v}"))
+            .collect();
+        if items.is_empty() {
+            tr(locale, "stats-none", &[])
+        } else {
+            items.join(", ")
+        }
+    };
+    let syncs: u64 = group(counters, "sync").iter().map(|(_, v)| v).sum();
+    let (hits, misses) = (get("cache|hit"), get("cache|miss"));
+    let rate = if hits + misses == 0 {
+        0.0
+    } else {
+        hits as f64 * 100.0 / (hits + misses) as f64
+    };
+    let lines = [
+        tr(
+            locale,
+            "stats-syncs",
+            &[
+                ("total", &syncs.to_string()),
+                ("list", &list("sync", usize::MAX)),
+            ],
+        ),
+        tr(
+            locale,
+            "stats-cache",
+            &[
+                ("hits", &hits.to_string()),
+                ("misses", &misses.to_string()),
+                ("rate", &format!("{rate:.2}")),
+            ],
+        ),
+        tr(
+            locale,
+            "stats-uploaded",
+            &[
+                ("images", &get("upload|images").to_string()),
+                ("size", &format_size(get("upload|bytes"))),
+            ],
+        ),
+        tr(
+            locale,
+            "stats-search",
+            &[("list", &list("search", usize::MAX))],
+        ),
+        tr(
+            locale,
+            "stats-failures",
+            &[("list", &list("failure", usize::MAX))],
+        ),
+        tr(locale, "stats-chats", &[("list", &list("chat", TOP_CHATS))]),
+    ];
+    format!(
+        "{}\n{}",
+        bold(&escape(&tr(
+            locale,
+            "stats-title",
+            &[("period", period.as_str())]
+        ))),
+        escape(&lines.join("\n"))
+    )
+}
+
+/// Format bytes like `1.50 MiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for u in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = u;
+    }
+    format!("{size:.2} {unit}")
+}
+
+fn default_similarity(chat: &Chat) -> u8 {
+    if chat.is_private() {
+        MIN_SIMILARITY_PRIVATE
+    } else {
+        MIN_SIMILARITY
+    }
+}
+
+fn inline_article(idx: usize, record: &CacheRecord) -> InlineQueryResult {
+    let title = if record.title.is_empty() {
+        &record.link
+    } else {
+        &record.title
+    };
+    InlineQueryResult::Article(
+        InlineQueryResultArticle::new(
+            idx.to_string(),
+            title,
+            InputMessageContent::Text(InputMessageContentText::new(format!(
+                "{title}\n{}",
+                record.url
+            ))),
+        )
+        .description(&record.url),
+    )
+}
+
+/// Target of editing the message.
+fn message_target(msg: &Message) -> TargetMessage {
+    TargetMessage::Common {
+        chat_id: msg.chat.id.into(),
+        message_id: msg.id,
+    }
+}
+
+/// Edit text of the message, which may be sent in inline mode.
+async fn edit_target(
+    bot: &AutoSend<DefaultParseMode<Bot>>,
+    target: &TargetMessage,
+    text: String,
+    keyboard: Option<InlineKeyboardMarkup>,
+) -> Result<(), teloxide::RequestError> {
+    match target {
+        TargetMessage::Common {
+            chat_id,
+            message_id,
+        } => {
+            let mut req = bot.edit_message_text(chat_id.clone(), *message_id, text);
+            if let Some(keyboard) = keyboard {
+                req = req.reply_markup(keyboard);
+            }
+            req.await.map(|_| ())
+        }
+        TargetMessage::Inline { inline_message_id } => {
+            let mut req = bot.edit_message_text_inline(inline_message_id, text);
+            if let Some(keyboard) = keyboard {
+                req = req.reply_markup(keyboard);
             }
-            _ => Err(anyhow::anyhow!("no matching collector")),
+            req.await.map(|_| ())
         }
     }
 }
+
+/// File name of the archive, with characters invalid on common file systems replaced.
+fn archive_name(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .take(100)
+        .collect();
+    let name = name.trim();
+    if name.is_empty() {
+        "gallery.cbz".to_string()
+    } else {
+        format!("{name}.cbz")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls() {
+        let text = "https://e-hentai.org/g/1/abc/ and https://nhentai.net/g/2 \
+            again https://e-hentai.org/g/1/abc/";
+        let urls = extract_urls(Some(text), None);
+        assert_eq!(
+            urls,
+            vec!["https://e-hentai.org/g/1/abc", "https://nhentai.net/g/2"]
+        );
+        assert!(extract_urls(Some("nothing here"), None).is_empty());
+
+        // caption with a url entity and a text link
+        let caption = "看 https://nhentai.net/g/3 and this";
+        let entities: Vec<MessageEntity> = serde_json::from_str(
+            r#"[{"type":"url","offset":2,"length":23},
+                {"type":"text_link","offset":30,"length":4,"url":"https://e-hentai.org/g/4/def/"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            extract_urls(Some(caption), Some(&entities)),
+            vec!["https://nhentai.net/g/3", "https://e-hentai.org/g/4/def"]
+        );
+    }
+
+    fn message(media: &str) -> Message {
+        serde_json::from_str(&format!(
+            r#"{{"message_id":1,"date":1650000000,"chat":{{"id":1,"type":"private","first_name":"a"}},"from":{{"id":1,"is_bot":false,"first_name":"a"}},{media}}}"#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn image() {
+        let photo = message(
+            r#""photo":[{"file_id":"small","file_unique_id":"s","width":90,"height":90},
+                {"file_id":"large","file_unique_id":"l","width":1280,"height":1280}]"#,
+        );
+        assert_eq!(image_file_id(&photo), Some("large"));
+
+        let document = |mime: &str, size: u32| {
+            message(&format!(
+                r#""document":{{"file_id":"doc","file_unique_id":"d","mime_type":"{mime}","file_size":{size}}}"#
+            ))
+        };
+        assert_eq!(image_file_id(&document("image/png", 1024)), Some("doc"));
+        assert_eq!(image_file_id(&document("application/zip", 1024)), None);
+        assert_eq!(
+            image_file_id(&document("image/png", MAX_IMAGE_DOCUMENT_SIZE + 1)),
+            None
+        );
+
+        let sticker = |animated: bool| {
+            message(&format!(
+                r#""sticker":{{"file_id":"sticker","file_unique_id":"s","width":512,"height":512,"is_animated":{animated},"is_video":false,
+                    "thumb":{{"file_id":"thumb","file_unique_id":"t","width":128,"height":128}}}}"#
+            ))
+        };
+        assert_eq!(image_file_id(&sticker(false)), Some("sticker"));
+        assert_eq!(image_file_id(&sticker(true)), Some("thumb"));
+
+        let animation = message(
+            r#""animation":{"file_id":"gif","file_unique_id":"g","width":320,"height":240,"duration":3,"mime_type":"video/mp4",
+                "thumb":{"file_id":"thumb","file_unique_id":"t","width":90,"height":90}},
+                "document":{"file_id":"gif","file_unique_id":"g","mime_type":"video/mp4"}"#,
+        );
+        assert_eq!(image_file_id(&animation), Some("thumb"));
+
+        assert_eq!(image_file_id(&message(r#""text":"hello""#)), None);
+    }
+
+    #[test]
+    fn votes() {
+        let m = |url: &str, score: u8| (url.to_string(), score);
+        assert_eq!(vote(&[]), None);
+        // more votes win over higher scores
+        assert_eq!(
+            vote(&[m("a", 90), m("b", 70), m("b", 80)]),
+            Some(("b".to_string(), 75))
+        );
+        // then higher total scores
+        assert_eq!(vote(&[m("a", 80), m("b", 90)]), Some(("b".to_string(), 90)));
+        // then the first matched
+        assert_eq!(
+            vote(&[m("a", 80), m("b", 80), m("c", 80)]),
+            Some(("a".to_string(), 80))
+        );
+    }
+
+    #[test]
+    fn batch() {
+        let mut batch = Batch {
+            locale: Locale::En,
+            truncated: false,
+            items: vec![
+                ("https://nhentai.net/g/1".to_string(), BatchState::Pending),
+                (
+                    "https://nhentai.net/g/2".to_string(),
+                    BatchState::Failed("not found".to_string()),
+                ),
+            ],
+        };
+        assert!(batch.text().starts_with("Syncing 2 links, 1 done"));
+        batch.items[0].1 = BatchState::Done("https://telegra.ph/a".to_string());
+        assert!(batch
+            .text()
+            .starts_with("Sync finished: 1 succeeded, 1 failed"));
+
+        // long errors are truncated to fit in one message
+        let batch = Batch {
+            locale: Locale::En,
+            truncated: true,
+            items: (0..MAX_BATCH_SIZE)
+                .map(|i| {
+                    (
+                        format!("https://nhentai.net/g/{i}"),
+                        BatchState::Failed("e".repeat(4096)),
+                    )
+                })
+                .collect(),
+        };
+        assert!(batch.text().chars().count() < 4096);
+    }
+
+    #[test]
+    fn stats() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536 * 1024), "1.50 MiB");
+
+        let counters: Counters = [
+            ("sync|ehentai", 3),
+            ("sync|nhentai", 1),
+            ("cache|hit", 1),
+            ("cache|miss", 3),
+            ("chat|-100", 2),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+        let text = stats_text(Locale::En, Period::Week, &counters);
+        assert!(text.starts_with("*Stats \\(week\\)*\n"));
+        assert!(text.contains("Syncs: 4 \\(ehentai 3, nhentai 1\\)"));
+        assert!(text.contains("hit rate 25\\.00%"));
+        assert!(text.contains("Failures: none"));
+        assert!(text.contains("Top chats: \\-100 2"));
+        let text = stats_text(Locale::Zh, Period::Week, &counters);
+        assert!(text.contains("同步: 4 \\(ehentai 3, nhentai 1\\)"));
+        assert!(text.contains("失败: 无"));
+    }
+
+    #[test]
+    fn archive() {
+        assert_eq!(
+            Action::decode("archive|https://nhentai.net/g/1"),
+            Some((Action::Archive, "https://nhentai.net/g/1"))
+        );
+        assert_eq!(archive_name("a/b: c?"), "a_b_ c_.cbz");
+        assert_eq!(archive_name("  "), "gallery.cbz");
+    }
+}
@@ -0,0 +1,223 @@
+//! Localized bot replies.
+//! Messages are Fluent files under `locales`, and missing messages fall back
+//! to english.
+//! The locale of a user is the one set by `/lang`, or Telegram's
+//! `language_code` if not set.
+use std::collections::HashMap;
+
+use eh2telegraph::storage::KVStorage;
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use teloxide::utils::markdown::escape;
+
+const KEY_PREFIX: &str = "lang|";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Locale {
+    En,
+    Zh,
+    Ja,
+}
+
+impl Locale {
+    pub const ALL: [Self; 3] = [Self::En, Self::Zh, Self::Ja];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Zh => "zh",
+            Locale::Ja => "ja",
+        }
+    }
+
+    /// Parse IETF language tag like `zh-hans`.
+    pub fn from_code(code: &str) -> Option<Self> {
+        let lang = code
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        Self::ALL.into_iter().find(|l| l.code() == lang)
+    }
+
+    fn source(self) -> &'static str {
+        match self {
+            Locale::En => include_str!("../locales/en.ftl"),
+            Locale::Zh => include_str!("../locales/zh.ftl"),
+            Locale::Ja => include_str!("../locales/ja.ftl"),
+        }
+    }
+}
+
+static BUNDLES: Lazy<HashMap<Locale, FluentBundle<FluentResource>>> = Lazy::new(|| {
+    Locale::ALL
+        .into_iter()
+        .map(|locale| {
+            let resource = FluentResource::try_new(locale.source().to_string())
+                .unwrap_or_else(|_| panic!("invalid fluent file of {}", locale.code()));
+            let lang = locale.code().parse().expect("invalid language code");
+            let mut bundle = FluentBundle::new_concurrent(vec![lang]);
+            // isolating marks break markdown escaping and look odd in Telegram
+            bundle.set_use_isolating(false);
+            bundle
+                .add_resource(resource)
+                .unwrap_or_else(|_| panic!("duplicated message in {}", locale.code()));
+            (locale, bundle)
+        })
+        .collect()
+});
+
+fn format(locale: Locale, id: &str, args: Option<&FluentArgs>) -> Option<String> {
+    let bundle = BUNDLES.get(&locale)?;
+    let pattern = bundle.get_message(id)?.value()?;
+    let mut errors = Vec::new();
+    let text = bundle.format_pattern(pattern, args, &mut errors);
+    if !errors.is_empty() {
+        tracing::warn!("[i18n] format {id} in {} failed: {errors:?}", locale.code());
+    }
+    Some(text.into_owned())
+}
+
+/// Translate the message. Returns the id itself if it is not found.
+pub fn tr(locale: Locale, id: &str, args: &[(&str, &str)]) -> String {
+    let mut fluent_args = FluentArgs::new();
+    for (k, v) in args {
+        fluent_args.set(*k, v.to_string());
+    }
+    let args = if args.is_empty() {
+        None
+    } else {
+        Some(&fluent_args)
+    };
+    format(locale, id, args)
+        .or_else(|| format(Locale::En, id, args))
+        .unwrap_or_else(|| id.to_string())
+}
+
+/// Translate into escaped MarkdownV2.
+/// Values of `md` are formatted markdown and inserted as is.
+pub fn tr_md(locale: Locale, id: &str, args: &[(&str, &str)], md: &[(&str, &str)]) -> String {
+    // private use characters are kept by both fluent and escaping
+    let placeholders: Vec<_> = (0..md.len())
+        .map(|i| format!("\u{e000}{i}\u{e001}"))
+        .collect();
+    let all: Vec<_> = args
+        .iter()
+        .copied()
+        .chain(
+            md.iter()
+                .zip(placeholders.iter())
+                .map(|((k, _), p)| (*k, p.as_str())),
+        )
+        .collect();
+    let mut text = escape(&tr(locale, id, &all));
+    for ((_, v), p) in md.iter().zip(placeholders.iter()) {
+        text = text.replace(p, v);
+    }
+    text
+}
+
+/// Help text generated from commands and their descriptions.
+pub fn help(locale: Locale) -> String {
+    let mut text = tr(locale, "help-intro", &[]);
+    for (cmd, id) in [
+        ("help", "cmd-help"),
+        ("version", "cmd-version"),
+        ("id", "cmd-id"),
+        ("sync", "cmd-sync"),
+        ("settings", "cmd-settings"),
+        ("lang", "cmd-lang"),
+    ] {
+        text.push_str(&format!("\n/{cmd} — {}", tr(locale, id, &[])));
+    }
+    text
+}
+
+/// Locales set by users with `/lang`.
+pub struct LocaleStore<S> {
+    cache: RwLock<HashMap<i64, Option<Locale>>>,
+    storage: S,
+}
+
+impl<S> LocaleStore<S>
+where
+    S: KVStorage<String>,
+{
+    pub fn new(storage: S) -> Self {
+        Self {
+            cache: Default::default(),
+            storage,
+        }
+    }
+
+    pub async fn get(&self, user: i64) -> anyhow::Result<Option<Locale>> {
+        if let Some(l) = self.cache.read().get(&user) {
+            return Ok(*l);
+        }
+        let locale = self
+            .storage
+            .get(&format!("{KEY_PREFIX}{user}"))
+            .await?
+            .and_then(|code| Locale::from_code(&code));
+        self.cache.write().insert(user, locale);
+        Ok(locale)
+    }
+
+    pub async fn set(&self, user: i64, locale: Locale) -> anyhow::Result<()> {
+        self.storage
+            .set(
+                format!("{KEY_PREFIX}{user}"),
+                locale.code().to_string(),
+                None,
+            )
+            .await?;
+        self.cache.write().insert(user, Some(locale));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locale() {
+        assert_eq!(Locale::from_code("zh-hans"), Some(Locale::Zh));
+        assert_eq!(Locale::from_code("ja"), Some(Locale::Ja));
+        assert_eq!(Locale::from_code("EN_us"), Some(Locale::En));
+        assert_eq!(Locale::from_code("fr"), None);
+    }
+
+    #[test]
+    fn translate() {
+        // all locales are valid and have the same messages
+        for locale in Locale::ALL {
+            let bundle = &BUNDLES[&locale];
+            let ids = Locale::En
+                .source()
+                .lines()
+                .filter(|l| l.starts_with(|c: char| c.is_ascii_lowercase()))
+                .filter_map(|l| l.split_once(" ="));
+            for (id, _) in ids {
+                assert!(bundle.has_message(id), "{id} missing in {}", locale.code());
+            }
+        }
+
+        assert_eq!(
+            tr(Locale::En, "syncing", &[("url", "https://a.b/")]),
+            "Syncing url https://a.b/"
+        );
+        assert_eq!(tr(Locale::Ja, "not-exist", &[]), "not-exist");
+        assert_eq!(
+            tr_md(
+                Locale::En,
+                "sync-finished",
+                &[],
+                &[("page", "[a](https://a.b/)")]
+            ),
+            "Sync to telegraph finished: [a](https://a.b/)"
+        );
+        assert!(help(Locale::Zh).contains("/sync"));
+    }
+}
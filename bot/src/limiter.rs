@@ -0,0 +1,282 @@
+//! Rate limits and daily quotas of syncing.
+//! Rate limits use in-memory token buckets, and daily quotas are saved in
+//! storage so they survive restarts.
+//! Storage has no atomic increment, so quotas are read and written under a
+//! lock. It is only held in process: bots sharing the storage may exceed the
+//! quotas slightly when syncing at the same time.
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use eh2telegraph::storage::KVStorage;
+use parking_lot::Mutex;
+
+pub const CONFIG_KEY: &str = "limit";
+
+const DAY_SECS: u64 = 24 * 3600;
+// buckets are cleaned when there are too many of them
+const MAX_BUCKETS: usize = 10240;
+
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+pub struct BucketConfig {
+    /// Max burst.
+    pub capacity: u32,
+    /// Seconds to refill one token.
+    pub refill_secs: u32,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct LimitConfig {
+    pub user: Option<BucketConfig>,
+    pub chat: Option<BucketConfig>,
+    /// Max syncs per user per day(UTC).
+    pub user_daily: Option<u32>,
+    /// Max syncs per chat per day(UTC).
+    pub chat_daily: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Scope {
+    User(i64),
+    Chat(i64),
+}
+
+impl Scope {
+    fn quota_key(&self, day: u64) -> String {
+        match self {
+            Scope::User(id) => format!("quota|{day}|user|{id}"),
+            Scope::Chat(id) => format!("quota|{day}|chat|{id}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, config: &BucketConfig, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed / config.refill_secs.max(1) as f64).min(config.capacity as f64);
+        self.updated = now;
+    }
+
+    /// Time to wait for the next token.
+    fn wait(&self, config: &BucketConfig) -> Option<Duration> {
+        if self.tokens >= 1.0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64(
+            (1.0 - self.tokens) * config.refill_secs.max(1) as f64,
+        ))
+    }
+}
+
+pub struct Limiter<S> {
+    config: LimitConfig,
+    buckets: Mutex<HashMap<Scope, Bucket>>,
+    storage: S,
+    /// Held while reading and writing quotas.
+    quota_lock: tokio::sync::Mutex<()>,
+}
+
+impl<S> Limiter<S>
+where
+    S: KVStorage<String>,
+{
+    pub fn new(config: LimitConfig, storage: S) -> Self {
+        Self {
+            config,
+            buckets: Default::default(),
+            storage,
+            quota_lock: Default::default(),
+        }
+    }
+
+    /// Take one sync for the user in the chat.
+    /// Returns the time to wait if it is limited.
+    pub async fn acquire(&self, user: Option<i64>, chat: i64) -> anyhow::Result<Option<Duration>> {
+        let mut scopes = Vec::with_capacity(2);
+        if let Some(user) = user {
+            scopes.push((Scope::User(user), self.config.user, self.config.user_daily));
+        }
+        // in private chat, chat id is the same as user id
+        if user != Some(chat) {
+            scopes.push((Scope::Chat(chat), self.config.chat, self.config.chat_daily));
+        }
+
+        // check rate limits, and reserve tokens in the same lock
+        let now = Instant::now();
+        {
+            let mut buckets = self.buckets.lock();
+            for (scope, bucket_config, _) in scopes.iter() {
+                if let Some(c) = bucket_config {
+                    let bucket = buckets.entry(*scope).or_insert(Bucket {
+                        tokens: c.capacity as f64,
+                        updated: now,
+                    });
+                    bucket.refill(c, now);
+                    if let Some(wait) = bucket.wait(c) {
+                        return Ok(Some(wait));
+                    }
+                }
+            }
+            for (scope, bucket_config, _) in scopes.iter() {
+                if bucket_config.is_some() {
+                    if let Some(b) = buckets.get_mut(scope) {
+                        b.tokens -= 1.0;
+                    }
+                }
+            }
+            if buckets.len() > MAX_BUCKETS {
+                // full buckets are the same as new ones
+                let (user, chat) = (self.config.user, self.config.chat);
+                buckets.retain(|scope, b| {
+                    let c = match scope {
+                        Scope::User(_) => user,
+                        Scope::Chat(_) => chat,
+                    };
+                    c.map(|c| {
+                        b.refill(&c, now);
+                        b.tokens < c.capacity as f64
+                    })
+                    .unwrap_or_default()
+                });
+            }
+        }
+
+        let r = self.consume_quota(&scopes).await;
+        if !matches!(r, Ok(None)) {
+            // give back the reserved tokens
+            let mut buckets = self.buckets.lock();
+            for (scope, bucket_config, _) in scopes.iter() {
+                if let (Some(c), Some(b)) = (bucket_config, buckets.get_mut(scope)) {
+                    b.tokens = (b.tokens + 1.0).min(c.capacity as f64);
+                }
+            }
+        }
+        r
+    }
+
+    /// Check and increase daily quotas.
+    async fn consume_quota(
+        &self,
+        scopes: &[(Scope, Option<BucketConfig>, Option<u32>)],
+    ) -> anyhow::Result<Option<Duration>> {
+        let _quota_guard = self.quota_lock.lock().await;
+        let now_secs = now_secs();
+        let day = now_secs / DAY_SECS;
+        let mut counts = Vec::with_capacity(scopes.len());
+        for (scope, _, daily) in scopes.iter() {
+            if let Some(daily) = daily {
+                let key = scope.quota_key(day);
+                let count: u32 = self
+                    .storage
+                    .get(&key)
+                    .await?
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_default();
+                if count >= *daily {
+                    return Ok(Some(Duration::from_secs(DAY_SECS - now_secs % DAY_SECS)));
+                }
+                counts.push((key, count));
+            }
+        }
+        for (key, count) in counts {
+            self.storage
+                .set(key, (count + 1).to_string(), Some(2 * DAY_SECS as usize))
+                .await?;
+        }
+        Ok(None)
+    }
+}
+
+#[inline]
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before unix epoch")
+        .as_secs()
+}
+
+/// Format duration like `1h 2m 3s`.
+pub fn format_wait(wait: Duration) -> String {
+    let secs = wait.as_secs().max(1);
+    let (h, m, s) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    let mut parts = Vec::with_capacity(3);
+    if h > 0 {
+        parts.push(format!("{h}h"));
+    }
+    if m > 0 {
+        parts.push(format!("{m}m"));
+    }
+    if s > 0 {
+        parts.push(format!("{s}s"));
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use eh2telegraph::storage::SimpleMemStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn limit() {
+        let config = LimitConfig {
+            user: Some(BucketConfig {
+                capacity: 2,
+                refill_secs: 60,
+            }),
+            chat: None,
+            user_daily: None,
+            chat_daily: Some(3),
+        };
+        let limiter = Limiter::new(config, SimpleMemStorage::default());
+        assert_eq!(limiter.acquire(Some(1), -1).await.unwrap(), None);
+        assert_eq!(limiter.acquire(Some(1), -1).await.unwrap(), None);
+        // bucket of user 1 is empty
+        let wait = limiter.acquire(Some(1), -1).await.unwrap().unwrap();
+        assert!(wait > Duration::from_secs(50));
+        // daily quota of chat -1 is used up
+        assert_eq!(limiter.acquire(Some(2), -1).await.unwrap(), None);
+        assert!(limiter.acquire(Some(3), -1).await.unwrap().is_some());
+        // tokens are given back when the quota is used up
+        assert_eq!(limiter.acquire(Some(3), -2).await.unwrap(), None);
+        assert_eq!(limiter.acquire(Some(3), -3).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn concurrent() {
+        let config = LimitConfig {
+            user: Some(BucketConfig {
+                capacity: 1,
+                refill_secs: 60,
+            }),
+            chat: None,
+            user_daily: Some(10),
+            chat_daily: None,
+        };
+        let limiter = Limiter::new(config, SimpleMemStorage::default());
+        let (a, b) = tokio::join!(limiter.acquire(Some(1), 1), limiter.acquire(Some(1), 1));
+        assert_eq!(
+            [a.unwrap(), b.unwrap()]
+                .iter()
+                .filter(|w| w.is_none())
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(format_wait(Duration::from_secs(3723)), "1h 2m 3s");
+        assert_eq!(format_wait(Duration::from_millis(10)), "1s");
+        assert_eq!(format_wait(Duration::from_secs(120)), "2m");
+    }
+}
@@ -4,7 +4,12 @@ use eh2telegraph::{
     collector::Registry,
     config::{self},
     http_proxy::ProxiedClient,
-    storage,
+    searcher::{
+        aggregate::AggregatedSearcher,
+        local::{LocalSearcher, PHashIndex},
+    },
+    stats::Stats,
+    storage::{self, KVStorage},
     sync::Synchronizer,
     telegraph::Telegraph,
 };
@@ -16,7 +21,7 @@ use teloxide::{
     dispatching::update_listeners,
     error_handlers::IgnoringErrorHandler,
     prelude2::*,
-    types::{AllowedUpdate, ChatPermissions, ParseMode, UpdateKind},
+    types::{AllowedUpdate, CallbackQuery, ChatPermissions, InlineQuery, ParseMode, UpdateKind},
 };
 
 use handler::{Command, Handler};
@@ -26,9 +31,20 @@ use crate::{
     util::{wrap_endpoint, PrettyChat},
 };
 
+mod acl;
+mod alert;
+mod api;
 mod handler;
+mod i18n;
+mod limiter;
+mod monitor;
+mod poster;
+mod settings;
 mod util;
 mod version;
+mod webhook;
+
+const STATS_FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
 
 #[derive(Debug, serde::Deserialize)]
 pub struct BaseConfig {
@@ -36,6 +52,10 @@ pub struct BaseConfig {
     pub telegraph: TelegraphConfig,
     #[serde(default)]
     pub admins: Vec<i64>,
+    /// Preferred gallery language(like chinese or english) when a photo
+    /// appears in multiple galleries.
+    #[serde(default)]
+    pub preferred_language: Option<String>,
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -50,6 +70,26 @@ pub struct TelegraphConfig {
 struct Args {
     #[clap(short, long, help = "Config file path")]
     config: Option<String>,
+    #[clap(long, help = "Export cache to a JSON Lines file and exit")]
+    export_cache: Option<String>,
+    #[clap(long, help = "Import cache from a JSON Lines file and exit")]
+    import_cache: Option<String>,
+    #[clap(
+        long,
+        help = "Only export cache keys with this prefix",
+        default_value = ""
+    )]
+    cache_prefix: String,
+    #[clap(
+        long,
+        help = "Export cache from the worker KV in this config file instead, for migrating"
+    )]
+    from_config: Option<String>,
+    #[clap(
+        long,
+        help = "Import cache into the worker KV in this config file instead, for migrating"
+    )]
+    to_config: Option<String>,
 }
 
 #[tokio::main]
@@ -62,7 +102,7 @@ async fn main() {
     tracing_subscriber::fmt().with_timer(timer).init();
     tracing::info!("initializing...");
 
-    config::init(args.config);
+    config::init(args.config.clone());
     let base_config: BaseConfig = config::parse("base")
         .expect("unable to parse base config")
         .expect("base config can not be empty");
@@ -76,14 +116,139 @@ async fn main() {
     #[cfg(not(debug_assertions))]
     let cache =
         storage::cloudflare_kv::CFStorage::new_from_config().expect("unable to build storage");
-    let mut synchronizer = Synchronizer::new(telegraph, registry, cache);
+    if args.export_cache.is_some() || args.import_cache.is_some() {
+        migrate_cache(&cache, &args)
+            .await
+            .expect("unable to migrate cache");
+        return;
+    }
+    // load hash index in background since it may take a while
+    let phash_index = PHashIndex::new(cache.clone());
+    let index = phash_index.clone();
+    tokio::spawn(async move {
+        if let Err(e) = index.load().await {
+            tracing::error!("unable to load phash index: {e:?}");
+        }
+    });
+    let stats = std::sync::Arc::new(Stats::default());
+    let searcher =
+        AggregatedSearcher::new_from_config_with_local(LocalSearcher::new(phash_index.clone()))
+            .with_stats(stats.clone());
+
+    let limit_config: Option<limiter::LimitConfig> =
+        config::parse(limiter::CONFIG_KEY).expect("unable to parse limit config");
+    let limiter = limit_config.map(|c| limiter::Limiter::new(c, cache.clone()));
+    let acl_config: Option<acl::AclConfig> =
+        config::parse(acl::CONFIG_KEY).expect("unable to parse acl config");
+    let acl = acl_config.map(|c| acl::Acl::new(c, cache.clone()));
+    if let Some(acl) = &acl {
+        // rules in config still apply
+        if let Err(e) = acl.load().await {
+            tracing::error!("unable to load acl rules: {e:?}");
+        }
+    }
+    let settings = settings::SettingsStore::new(cache.clone());
+    let locales = i18n::LocaleStore::new(cache.clone());
+
+    let bot_token = base_config.bot_token.clone();
+    let bot = Bot::new(base_config.bot_token)
+        .parse_mode(ParseMode::MarkdownV2)
+        .auto_send();
+    let channel_config: Option<poster::ChannelConfig> =
+        config::parse(poster::CONFIG_KEY).expect("unable to parse channel config");
+    let poster = channel_config.map(|c| poster::Poster::new(bot.clone(), c, cache.clone()));
+    let alert_config: Option<alert::AlertConfig> =
+        config::parse(alert::CONFIG_KEY).expect("unable to parse alert config");
+    let alerter =
+        alert_config.map(|c| alert::Alerter::new(bot.clone(), c, base_config.admins.clone()));
+
+    let webhook_config: Option<webhook::WebhookConfig> =
+        config::parse(webhook::CONFIG_KEY).expect("unable to parse webhook config");
+    let monitor_config: Option<monitor::MonitorConfig> =
+        config::parse(monitor::CONFIG_KEY).expect("unable to parse monitor config");
+    let health = monitor_config.as_ref().map(|c| {
+        let mode = if webhook_config.is_some() {
+            "webhook"
+        } else {
+            "polling"
+        };
+        std::sync::Arc::new(monitor::Health::new(bot.clone(), cache.clone(), mode, c))
+    });
+    if let (Some(config), Some(health)) = (&monitor_config, &health) {
+        monitor::serve(config, health.clone())
+            .await
+            .expect("unable to start monitor server");
+    }
+
+    let mut synchronizer = Synchronizer::new(telegraph, registry, cache.clone())
+        .with_phash_index(phash_index)
+        .with_stats(stats);
     if telegraph_config.author_name.is_some() {
         synchronizer =
             synchronizer.with_author(telegraph_config.author_name, telegraph_config.author_url);
     }
 
     let admins = base_config.admins.into_iter().collect();
-    let handler = Box::leak(Box::new(Handler::new(synchronizer, admins))) as &Handler<_>;
+    let mut handler = Handler::new(synchronizer, searcher, admins)
+        .with_preferred_language(base_config.preferred_language)
+        .with_settings(settings)
+        .with_locales(locales)
+        .with_archives(cache);
+    if let Some(limiter) = limiter {
+        handler = handler.with_limiter(limiter);
+    }
+    if let Some(acl) = acl {
+        handler = handler.with_acl(acl);
+    }
+    if let Some(alerter) = alerter {
+        handler = handler.with_alerter(alerter);
+    }
+    if let Some(poster) = poster {
+        handler = handler.with_poster(poster);
+    }
+    let handler = Box::leak(Box::new(handler)) as &Handler<_>;
+
+    let api_config: Option<api::ApiConfig> =
+        config::parse(api::CONFIG_KEY).expect("unable to parse api config");
+    if let Some(config) = &api_config {
+        api::serve(config, handler)
+            .await
+            .expect("unable to start api server");
+    }
+
+    // load cache records in background for inline searching
+    tokio::spawn(async move {
+        if let Err(e) = handler.synchronizer.load_records().await {
+            tracing::error!("unable to load cache records: {e:?}");
+        }
+    });
+
+    // save usage stats and cache records periodically
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(STATS_FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = handler.synchronizer.flush_stats().await {
+                tracing::error!("unable to save usage stats: {e:?}");
+            }
+            if let Err(e) = handler.synchronizer.save_records().await {
+                tracing::error!("unable to save cache records: {e:?}");
+            }
+        }
+    });
+
+    // send error digest to admins daily
+    if let Some(alerter) = handler.alerter.as_ref().filter(|a| a.digest) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(alert::DIGEST_INTERVAL);
+            // the first tick completes immediately
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                alerter.send_digest();
+            }
+        });
+    }
 
     // === Bot related ===
     let command_handler = move |bot: AutoSend<DefaultParseMode<Bot>>,
@@ -105,6 +270,12 @@ async fn main() {
     let photo_handler = move |bot: AutoSend<DefaultParseMode<Bot>>, message: Message| async move {
         handler.respond_photo(bot, message).await
     };
+    let inline_handler = move |bot: AutoSend<DefaultParseMode<Bot>>, query: InlineQuery| async move {
+        handler.respond_inline(bot, query).await
+    };
+    let callback_handler = move |bot: AutoSend<DefaultParseMode<Bot>>, query: CallbackQuery| async move {
+        handler.respond_callback(bot, query).await
+    };
     let default_handler = move |bot: AutoSend<DefaultParseMode<Bot>>, message: Message| async move {
         handler.respond_default(bot, message).await
     };
@@ -127,69 +298,103 @@ async fn main() {
         }
     };
 
-    let bot = Bot::new(base_config.bot_token)
-        .parse_mode(ParseMode::MarkdownV2)
-        .auto_send();
+    let message_handler = dptree::entry()
+        .chain(dptree::filter_map(move |update: Update| {
+            match update.kind {
+                UpdateKind::Message(x) | UpdateKind::EditedMessage(x) => Some(x),
+                _ => None,
+            }
+        }))
+        .chain(dptree::filter_map_async(permission_filter))
+        .chain(dptree::filter(move |message: Message| {
+            let mut ids = vec![message.chat.id];
+            ids.extend(message.from().map(|u| u.id));
+            handler.is_allowed(&ids)
+        }))
+        .branch(
+            dptree::entry()
+                .chain(dptree::filter(move |message: Message| {
+                    handler.admins.contains(&message.chat.id)
+                }))
+                .filter_command::<AdminCommand>()
+                .branch(wrap_endpoint(admin_command_handler)),
+        )
+        .branch(
+            dptree::entry()
+                .filter_command::<Command>()
+                .branch(wrap_endpoint(command_handler)),
+        )
+        .branch(
+            dptree::entry()
+                .chain(dptree::filter_map(move |message: Message| {
+                    // Ownership mechanism does not allow using map.
+                    #[allow(clippy::manual_map)]
+                    match message.text() {
+                        Some(v) if !v.is_empty() => Some(message),
+                        _ => None,
+                    }
+                }))
+                .branch(wrap_endpoint(text_handler)),
+        )
+        .branch(
+            dptree::entry()
+                .chain(dptree::filter_map(move |message: Message| {
+                    // Ownership mechanism does not allow using map.
+                    #[allow(clippy::manual_map)]
+                    match message.caption_entities() {
+                        Some(v) if !v.is_empty() => Some(message),
+                        _ => None,
+                    }
+                }))
+                .branch(wrap_endpoint(caption_handler)),
+        )
+        .branch(
+            dptree::entry()
+                .chain(dptree::filter(move |message: Message| {
+                    handler::image_file_id(&message).is_some()
+                }))
+                .branch(wrap_endpoint(photo_handler)),
+        )
+        .branch(wrap_endpoint(default_handler));
+    let inline_query_handler = dptree::entry()
+        .chain(dptree::filter_map(move |update: Update| {
+            match update.kind {
+                UpdateKind::InlineQuery(x) => Some(x),
+                _ => None,
+            }
+        }))
+        .chain(dptree::filter(move |query: InlineQuery| {
+            handler.is_allowed(&[query.from.id])
+        }))
+        .branch(wrap_endpoint(inline_handler));
+
+    let callback_query_handler = dptree::entry()
+        .chain(dptree::filter_map(move |update: Update| {
+            match update.kind {
+                UpdateKind::CallbackQuery(x) => Some(x),
+                _ => None,
+            }
+        }))
+        .chain(dptree::filter(move |query: CallbackQuery| {
+            let mut ids = vec![query.from.id];
+            ids.extend(query.message.as_ref().map(|m| m.chat.id));
+            handler.is_allowed(&ids)
+        }))
+        .branch(wrap_endpoint(callback_handler));
+
     let mut bot_dispatcher = Dispatcher::builder(
         bot.clone(),
         dptree::entry()
-            .chain(dptree::filter_map(move |update: Update| {
-                match update.kind {
-                    UpdateKind::Message(x) | UpdateKind::EditedMessage(x) => Some(x),
-                    _ => None,
+            // record liveness for health checks
+            .chain(dptree::filter(move |_: Update| {
+                if let Some(health) = &health {
+                    health.touch();
                 }
+                true
             }))
-            .chain(dptree::filter_map_async(permission_filter))
-            .branch(
-                dptree::entry()
-                    .chain(dptree::filter(move |message: Message| {
-                        handler.admins.contains(&message.chat.id)
-                    }))
-                    .filter_command::<AdminCommand>()
-                    .branch(wrap_endpoint(admin_command_handler)),
-            )
-            .branch(
-                dptree::entry()
-                    .filter_command::<Command>()
-                    .branch(wrap_endpoint(command_handler)),
-            )
-            .branch(
-                dptree::entry()
-                    .chain(dptree::filter_map(move |message: Message| {
-                        // Ownership mechanism does not allow using map.
-                        #[allow(clippy::manual_map)]
-                        match message.text() {
-                            Some(v) if !v.is_empty() => Some(message),
-                            _ => None,
-                        }
-                    }))
-                    .branch(wrap_endpoint(text_handler)),
-            )
-            .branch(
-                dptree::entry()
-                    .chain(dptree::filter_map(move |message: Message| {
-                        // Ownership mechanism does not allow using map.
-                        #[allow(clippy::manual_map)]
-                        match message.caption_entities() {
-                            Some(v) if !v.is_empty() => Some(message),
-                            _ => None,
-                        }
-                    }))
-                    .branch(wrap_endpoint(caption_handler)),
-            )
-            .branch(
-                dptree::entry()
-                    .chain(dptree::filter_map(move |message: Message| {
-                        // Ownership mechanism does not allow using map.
-                        #[allow(clippy::manual_map)]
-                        match message.photo() {
-                            Some(v) if !v.is_empty() => Some(message),
-                            _ => None,
-                        }
-                    }))
-                    .branch(wrap_endpoint(photo_handler)),
-            )
-            .branch(wrap_endpoint(default_handler)),
+            .branch(message_handler)
+            .branch(inline_query_handler)
+            .branch(callback_query_handler),
     )
     .default_handler(Box::new(|_upd| {
         #[cfg(debug_assertions)]
@@ -199,18 +404,76 @@ async fn main() {
     .error_handler(std::sync::Arc::new(IgnoringErrorHandler))
     .build();
     bot_dispatcher.setup_ctrlc_handler();
-    let bot_listener = update_listeners::polling(
-        bot,
-        Some(std::time::Duration::from_secs(10)),
-        None,
-        Some(vec![AllowedUpdate::Message]),
-    );
-
-    tracing::info!("initializing finished, bot is running");
-    bot_dispatcher
-        .dispatch_with_listener(
-            bot_listener,
-            LoggingErrorHandler::with_custom_text("An error from the update listener"),
-        )
-        .await;
+    let allowed_updates = vec![
+        AllowedUpdate::Message,
+        AllowedUpdate::InlineQuery,
+        AllowedUpdate::CallbackQuery,
+    ];
+    let error_handler = LoggingErrorHandler::with_custom_text("An error from the update listener");
+
+    match webhook_config {
+        Some(config) => {
+            let config = config.with_secret_token();
+            let (_, bot_listener) = webhook::listen(&config)
+                .await
+                .expect("unable to start webhook listener");
+            webhook::set_webhook(&bot_token, &config, &allowed_updates)
+                .await
+                .expect("unable to set webhook");
+            tracing::info!("initializing finished, bot is running with webhook");
+            bot_dispatcher
+                .dispatch_with_listener(bot_listener, error_handler)
+                .await;
+            if let Err(e) = bot.delete_webhook().await {
+                tracing::error!("unable to delete webhook: {e:?}");
+            }
+        }
+        None => {
+            let bot_listener = update_listeners::polling(
+                bot,
+                Some(std::time::Duration::from_secs(10)),
+                None,
+                Some(allowed_updates),
+            );
+            tracing::info!("initializing finished, bot is running");
+            bot_dispatcher
+                .dispatch_with_listener(bot_listener, error_handler)
+                .await;
+        }
+    }
+    if let Err(e) = handler.synchronizer.flush_stats().await {
+        tracing::error!("unable to save usage stats: {e:?}");
+    }
+    if let Err(e) = handler.synchronizer.save_records().await {
+        tracing::error!("unable to save cache records: {e:?}");
+    }
+}
+
+/// Export and import cache. With `--from-config` or `--to-config`, the worker KV
+/// of the given config file is used instead, so cache can be migrated between
+/// deployments in one run. Cloudflare worker KV is the only persistent backend.
+async fn migrate_cache<S: KVStorage<String>>(cache: &S, args: &Args) -> anyhow::Result<()> {
+    if let Some(path) = &args.export_cache {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let count = match &args.from_config {
+            Some(config) => {
+                let source = storage::cloudflare_kv::CFStorage::new_from_config_file(config)?;
+                storage::dump::export(&source, &args.cache_prefix, file).await?
+            }
+            None => storage::dump::export(cache, &args.cache_prefix, file).await?,
+        };
+        tracing::info!("exported {count} cache entries to {path}");
+    }
+    if let Some(path) = &args.import_cache {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let count = match &args.to_config {
+            Some(config) => {
+                let destination = storage::cloudflare_kv::CFStorage::new_from_config_file(config)?;
+                storage::dump::import(&destination, file).await?
+            }
+            None => storage::dump::import(cache, file).await?,
+        };
+        tracing::info!("imported {count} cache entries from {path}");
+    }
+    Ok(())
 }
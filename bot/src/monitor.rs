@@ -0,0 +1,209 @@
+//! Monitoring http server.
+//! `/metrics` exports prometheus metrics, and `/healthz` checks storage,
+//! Telegram api and update liveness so the bot can be restarted when wedged.
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use eh2telegraph::{metrics, storage::KVStorage};
+use hyper::{
+    header::CONTENT_TYPE, server::conn::Http, service::service_fn, Body, Method, Request, Response,
+    StatusCode,
+};
+use teloxide::{adaptors::DefaultParseMode, prelude2::*};
+use tokio::net::TcpListener;
+
+pub const CONFIG_KEY: &str = "monitor";
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+const HEALTH_KEY: &str = "healthz";
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MonitorConfig {
+    #[serde(default = "default_listen")]
+    pub listen: SocketAddr,
+    /// Report unhealthy if no update is received for this many seconds.
+    /// Only set it for bots which receive updates frequently.
+    #[serde(default)]
+    pub max_idle: Option<u64>,
+}
+
+// metrics and health checks are not authenticated, so they are only served locally by default
+fn default_listen() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 9090))
+}
+
+pub struct Health<S> {
+    bot: AutoSend<DefaultParseMode<Bot>>,
+    storage: S,
+    /// How updates are received, polling or webhook.
+    mode: &'static str,
+    max_idle: Option<Duration>,
+    /// Unix seconds of the last update.
+    last_update: AtomicU64,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Check {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Check {
+    fn from_result<T, E: std::fmt::Display>(
+        r: Result<Result<T, E>, tokio::time::error::Elapsed>,
+    ) -> Self {
+        let error = match r {
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(_) => Some("timeout".to_string()),
+        };
+        Self {
+            ok: error.is_none(),
+            error,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Report {
+    ok: bool,
+    mode: &'static str,
+    storage: Check,
+    telegram: Check,
+    updates: Check,
+    /// Seconds since the last update.
+    idle: u64,
+}
+
+impl<S> Health<S>
+where
+    S: KVStorage<String>,
+{
+    pub fn new(
+        bot: AutoSend<DefaultParseMode<Bot>>,
+        storage: S,
+        mode: &'static str,
+        config: &MonitorConfig,
+    ) -> Self {
+        Self {
+            bot,
+            storage,
+            mode,
+            max_idle: config.max_idle.map(Duration::from_secs),
+            last_update: AtomicU64::new(now_secs()),
+        }
+    }
+
+    /// Record that an update is received.
+    pub fn touch(&self) {
+        self.last_update.store(now_secs(), Ordering::Relaxed);
+    }
+
+    async fn check(&self) -> Report {
+        let storage = Check::from_result(
+            tokio::time::timeout(CHECK_TIMEOUT, self.storage.get(HEALTH_KEY)).await,
+        );
+        let telegram =
+            Check::from_result(tokio::time::timeout(CHECK_TIMEOUT, self.bot.get_me()).await);
+        let idle = now_secs().saturating_sub(self.last_update.load(Ordering::Relaxed));
+        let updates = match self.max_idle {
+            Some(max) if idle > max.as_secs() => Check {
+                ok: false,
+                error: Some(format!("no update received for {idle}s")),
+            },
+            _ => Check {
+                ok: true,
+                error: None,
+            },
+        };
+        Report {
+            ok: storage.ok && telegram.ok && updates.ok,
+            mode: self.mode,
+            storage,
+            telegram,
+            updates,
+            idle,
+        }
+    }
+}
+
+/// Start the monitoring server in background.
+pub async fn serve<S>(config: &MonitorConfig, health: Arc<Health<S>>) -> anyhow::Result<SocketAddr>
+where
+    S: KVStorage<String> + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(config.listen).await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::error!("[monitor] accept fail: {e}");
+                    continue;
+                }
+            };
+            let health = health.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |req| handle(health.clone(), req));
+                if let Err(e) = Http::new().serve_connection(stream, service).await {
+                    tracing::debug!("[monitor] serve connection fail: {e}");
+                }
+            });
+        }
+    });
+    tracing::info!("[monitor] listening on {addr}");
+    Ok(addr)
+}
+
+async fn handle<S>(health: Arc<Health<S>>, req: Request<Body>) -> Result<Response<Body>, Infallible>
+where
+    S: KVStorage<String>,
+{
+    if req.method() != Method::GET {
+        return Ok(status(StatusCode::METHOD_NOT_ALLOWED));
+    }
+    let resp = match req.uri().path() {
+        "/metrics" => Response::builder()
+            .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(metrics::encode())),
+        "/healthz" => {
+            let report = health.check().await;
+            let code = if report.ok {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            Response::builder()
+                .status(code)
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    serde_json::to_string(&report).expect("unable to serialize health report"),
+                ))
+        }
+        _ => return Ok(status(StatusCode::NOT_FOUND)),
+    };
+    Ok(resp.expect("unable to build response"))
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    let mut resp = Response::new(Body::empty());
+    *resp.status_mut() = code;
+    resp
+}
+
+#[inline]
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before unix epoch")
+        .as_secs()
+}
@@ -0,0 +1,197 @@
+//! Post synced galleries to a channel.
+//! Only galleries synced by trusted users(and admins) are posted, and posted
+//! galleries are saved in storage so nothing is posted twice.
+use std::collections::HashSet;
+
+use eh2telegraph::{collector::AlbumMeta, storage::KVStorage, sync::Synced};
+use reqwest::Url;
+use teloxide::{
+    adaptors::DefaultParseMode,
+    prelude2::*,
+    types::InputFile,
+    utils::markdown::{bold, escape, link},
+};
+
+pub const CONFIG_KEY: &str = "channel";
+
+const KEY_PREFIX: &str = "posted|";
+// limited by telegram
+const MAX_CAPTION_LEN: usize = 1024;
+const MAX_TAGS: usize = 20;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ChannelConfig {
+    /// Channel id, the bot must be an admin of it.
+    pub chat_id: i64,
+    #[serde(default)]
+    pub trusted: Vec<i64>,
+    /// Post with the cover image.
+    #[serde(default = "default_true")]
+    pub cover: bool,
+    /// Add tags as hashtags.
+    #[serde(default = "default_true")]
+    pub tags: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+pub struct Poster<S> {
+    bot: AutoSend<DefaultParseMode<Bot>>,
+    chat_id: i64,
+    trusted: HashSet<i64>,
+    cover: bool,
+    tags: bool,
+    storage: S,
+    /// Held while posting, so galleries synced by multiple users are posted once.
+    posting: tokio::sync::Mutex<()>,
+}
+
+impl<S> Poster<S>
+where
+    S: KVStorage<String>,
+{
+    pub fn new(bot: AutoSend<DefaultParseMode<Bot>>, config: ChannelConfig, storage: S) -> Self {
+        Self {
+            bot,
+            chat_id: config.chat_id,
+            trusted: config.trusted.into_iter().collect(),
+            cover: config.cover,
+            tags: config.tags,
+            storage,
+            posting: Default::default(),
+        }
+    }
+
+    pub fn is_trusted(&self, user: i64) -> bool {
+        self.trusted.contains(&user)
+    }
+
+    /// Post the synced gallery if it has not been posted.
+    /// Galleries synced from cache are skipped since they have been synced before.
+    pub async fn post(&self, synced: &Synced) -> anyhow::Result<()> {
+        let meta = match &synced.meta {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+        let key = format!("{KEY_PREFIX}{}", meta.link);
+        let _posting = self.posting.lock().await;
+        if self.storage.get(&key).await?.is_some() {
+            tracing::info!("[poster] {} has been posted", meta.link);
+            return Ok(());
+        }
+
+        let text = caption(meta, &synced.url, self.tags);
+        let cover = synced
+            .cover
+            .as_deref()
+            .filter(|_| self.cover)
+            .and_then(|c| Url::parse(c).ok());
+        let mut posted = false;
+        if let Some(cover) = cover {
+            match self
+                .bot
+                .send_photo(self.chat_id, InputFile::url(cover))
+                .caption(text.clone())
+                .await
+            {
+                Ok(_) => posted = true,
+                Err(e) => tracing::warn!("[poster] post with cover failed: {e}"),
+            }
+        }
+        // fallback to text message without cover
+        if !posted {
+            self.bot.send_message(self.chat_id, text).await?;
+        }
+
+        self.storage.set(key, synced.url.clone(), None).await?;
+        tracing::info!("[poster] posted {} to {}", meta.link, self.chat_id);
+        Ok(())
+    }
+}
+
+/// Convert tag like `female:big breasts` into `#big_breasts`.
+fn hashtag(tag: &str) -> Option<String> {
+    let name = tag.rsplit(':').next().unwrap_or(tag);
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let name = name.trim_matches('_');
+    if name.is_empty() {
+        None
+    } else {
+        Some(format!("#{name}"))
+    }
+}
+
+/// Caption in MarkdownV2 with title, tags, telegraph link and source link.
+fn caption(meta: &AlbumMeta, telegraph: &str, with_tags: bool) -> String {
+    let head = bold(&escape(&meta.name));
+    let tail = format!(
+        "{} \\| {}",
+        link(telegraph, "Telegraph"),
+        link(&meta.link, "Source")
+    );
+    let mut text = head;
+    if with_tags {
+        // tags are dropped if the caption is too long
+        let budget = MAX_CAPTION_LEN.saturating_sub(text.len() + tail.len() + 2);
+        let mut tags = String::new();
+        for tag in meta
+            .tags
+            .iter()
+            .flatten()
+            .filter_map(|t| hashtag(t))
+            .take(MAX_TAGS)
+        {
+            let tag = escape(&tag);
+            if tags.len() + tag.len() + 1 > budget {
+                break;
+            }
+            if !tags.is_empty() {
+                tags.push(' ');
+            }
+            tags.push_str(&tag);
+        }
+        if !tags.is_empty() {
+            text.push('\n');
+            text.push_str(&tags);
+        }
+    }
+    text.push('\n');
+    text.push_str(&tail);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags() {
+        assert_eq!(hashtag("female:big breasts").unwrap(), "#big_breasts");
+        assert_eq!(hashtag("full color").unwrap(), "#full_color");
+        assert_eq!(hashtag("artist:"), None);
+    }
+
+    #[test]
+    fn caption_text() {
+        let meta = AlbumMeta {
+            link: "https://e-hentai.org/g/1/abc".to_string(),
+            name: "Title [A]".to_string(),
+            class: None,
+            description: None,
+            authors: None,
+            tags: Some(vec!["female:big breasts".to_string()]),
+        };
+        let text = caption(&meta, "https://telegra.ph/a", true);
+        assert_eq!(
+            text,
+            "*Title \\[A\\]*\n\\#big\\_breasts\n[Telegraph](https://telegra.ph/a) \\| [Source](https://e-hentai.org/g/1/abc)"
+        );
+        let text = caption(&meta, "https://telegra.ph/a", false);
+        assert!(!text.contains('#'));
+    }
+}
@@ -0,0 +1,188 @@
+//! Per-chat settings.
+//! Settings are saved in storage as json, and chats without settings use the
+//! defaults which keep the original behavior.
+use std::{collections::HashMap, sync::Arc};
+
+use eh2telegraph::storage::KVStorage;
+use parking_lot::RwLock;
+
+use crate::i18n::{tr, Locale};
+
+const KEY_PREFIX: &str = "settings|";
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ChatSettings {
+    #[serde(default = "default_true")]
+    pub photo_search: bool,
+    #[serde(default = "default_true")]
+    pub link_detection: bool,
+    /// Overrides the default threshold.
+    #[serde(default)]
+    pub min_similarity: Option<u8>,
+    /// Overrides the preferred language in config.
+    #[serde(default)]
+    pub preferred_language: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for ChatSettings {
+    fn default() -> Self {
+        Self {
+            photo_search: true,
+            link_detection: true,
+            min_similarity: None,
+            preferred_language: None,
+        }
+    }
+}
+
+impl ChatSettings {
+    /// Apply `/settings key value`.
+    /// Returns message id of the error if it is invalid.
+    pub fn apply(&mut self, key: &str, value: &str) -> Result<(), &'static str> {
+        let value = value.trim();
+        match key {
+            "photo" => self.photo_search = parse_switch(value)?,
+            "link" => self.link_detection = parse_switch(value)?,
+            "similarity" => {
+                self.min_similarity = match value {
+                    "default" => None,
+                    v => match v.parse::<u8>() {
+                        Ok(v) if v <= 100 => Some(v),
+                        _ => return Err("settings-error-similarity"),
+                    },
+                }
+            }
+            "language" => {
+                self.preferred_language = match value {
+                    "" => return Err("settings-error-language"),
+                    "default" => None,
+                    v => Some(v.to_lowercase()),
+                }
+            }
+            _ => return Err("settings-error-unknown"),
+        }
+        Ok(())
+    }
+
+    pub fn describe(
+        &self,
+        locale: Locale,
+        default_similarity: u8,
+        default_language: Option<&str>,
+    ) -> String {
+        let switch = |v| tr(locale, if v { "settings-on" } else { "settings-off" }, &[]);
+        let default = |v: &str| tr(locale, "settings-default", &[("value", v)]);
+        let similarity = match self.min_similarity {
+            Some(v) => v.to_string(),
+            None => default(&default_similarity.to_string()),
+        };
+        let language = match (&self.preferred_language, default_language) {
+            (Some(v), _) => v.clone(),
+            (None, Some(v)) => default(v),
+            (None, None) => tr(locale, "settings-not-set", &[]),
+        };
+        [
+            ("settings-photo", switch(self.photo_search)),
+            ("settings-link", switch(self.link_detection)),
+            ("settings-similarity", similarity),
+            ("settings-language", language),
+        ]
+        .into_iter()
+        .map(|(id, value)| tr(locale, id, &[("value", &value)]))
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+}
+
+fn parse_switch(value: &str) -> Result<bool, &'static str> {
+    match value {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => Err("settings-error-switch"),
+    }
+}
+
+pub struct SettingsStore<S> {
+    cache: RwLock<HashMap<i64, Arc<ChatSettings>>>,
+    storage: S,
+}
+
+impl<S> SettingsStore<S>
+where
+    S: KVStorage<String>,
+{
+    pub fn new(storage: S) -> Self {
+        Self {
+            cache: Default::default(),
+            storage,
+        }
+    }
+
+    pub async fn get(&self, chat: i64) -> anyhow::Result<Arc<ChatSettings>> {
+        if let Some(s) = self.cache.read().get(&chat) {
+            return Ok(s.clone());
+        }
+        let settings = match self.storage.get(&format!("{KEY_PREFIX}{chat}")).await? {
+            Some(v) => serde_json::from_str(&v)?,
+            None => ChatSettings::default(),
+        };
+        let settings = Arc::new(settings);
+        self.cache.write().insert(chat, settings.clone());
+        Ok(settings)
+    }
+
+    pub async fn set(&self, chat: i64, settings: ChatSettings) -> anyhow::Result<()> {
+        self.storage
+            .set(
+                format!("{KEY_PREFIX}{chat}"),
+                serde_json::to_string(&settings)?,
+                None,
+            )
+            .await?;
+        self.cache.write().insert(chat, Arc::new(settings));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eh2telegraph::storage::SimpleMemStorage;
+
+    use super::*;
+
+    #[test]
+    fn apply() {
+        let mut s = ChatSettings::default();
+        s.apply("photo", "off").unwrap();
+        s.apply("similarity", "85").unwrap();
+        s.apply("language", "Chinese").unwrap();
+        assert!(!s.photo_search);
+        assert_eq!(s.min_similarity, Some(85));
+        assert_eq!(s.preferred_language.as_deref(), Some("chinese"));
+
+        assert!(s.apply("similarity", "101").is_err());
+        assert!(s.apply("link", "maybe").is_err());
+        assert!(s.apply("unknown", "on").is_err());
+        s.apply("similarity", "default").unwrap();
+        assert_eq!(s.min_similarity, None);
+    }
+
+    #[tokio::test]
+    async fn store() {
+        let storage = SimpleMemStorage::default();
+        let store = SettingsStore::new(storage.clone());
+        assert_eq!(*store.get(-1).await.unwrap(), ChatSettings::default());
+
+        let s = ChatSettings {
+            link_detection: false,
+            ..Default::default()
+        };
+        store.set(-1, s.clone()).await.unwrap();
+        let store = SettingsStore::new(storage);
+        assert_eq!(*store.get(-1).await.unwrap(), s);
+    }
+}
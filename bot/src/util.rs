@@ -1,6 +1,7 @@
 use std::{convert::Infallible, ops::ControlFlow, sync::Arc};
 
 use dptree::{di::Injectable, from_fn, Handler};
+use hyper::body::{Body, HttpBody};
 
 pub struct PrettyChat<'a>(pub &'a teloxide::types::Chat);
 
@@ -56,6 +57,35 @@ where
     })
 }
 
+/// Keep the first `max` chars of the text.
+pub fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((idx, _)) => format!("{}…", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+/// Read the whole body. Returns None if it is larger than the limit.
+pub async fn read_body(mut body: Body, limit: usize) -> Result<Option<Vec<u8>>, hyper::Error> {
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if buf.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(Some(buf))
+}
+
+/// Compare secrets in constant time, so they can not be guessed by timing.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
 #[macro_export]
 macro_rules! ok_or_break {
     ($e: expr) => {
@@ -0,0 +1,321 @@
+//! Webhook update listener.
+//! Telegram posts updates to a local http(s) server, which is useful when the
+//! bot is deployed behind a reverse proxy.
+use std::{convert::Infallible, fs::File, io::BufReader, net::SocketAddr, sync::Arc};
+
+use hyper::{server::conn::Http, service::service_fn, Body, Method, Request, Response, StatusCode};
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::{multipart, Url};
+use teloxide::{
+    dispatching::{
+        stop_token::{AsyncStopFlag, AsyncStopToken},
+        update_listeners::{StatefulListener, UpdateListener},
+    },
+    types::{AllowedUpdate, Update},
+};
+use tokio::{net::TcpListener, sync::mpsc};
+use tokio_rustls::{
+    rustls::{self, Certificate, PrivateKey},
+    TlsAcceptor,
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::util::{constant_time_eq, read_body};
+
+pub const CONFIG_KEY: &str = "webhook";
+const SECRET_TOKEN_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+// updates are small, larger bodies are not from telegram
+const MAX_UPDATE_SIZE: usize = 1024 * 1024;
+// telegram retries updates rejected when the queue is full
+const UPDATE_QUEUE_LEN: usize = 1024;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct WebhookConfig {
+    /// Public url which telegram posts updates to.
+    /// The listener serves the path of it.
+    pub url: String,
+    /// Local address to listen on.
+    #[serde(default = "default_listen")]
+    pub listen: SocketAddr,
+    /// Telegram will send it in header, and requests without it are rejected.
+    /// A random one is generated on startup if it is not set.
+    pub secret_token: Option<String>,
+    /// Serve https with the cert and key in pem format.
+    /// The cert is also uploaded to telegram so self-signed certs work.
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+}
+
+fn default_listen() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], 8080))
+}
+
+impl WebhookConfig {
+    /// Generate a random secret token if it is not set.
+    pub fn with_secret_token(mut self) -> Self {
+        if self.secret_token.is_none() {
+            let secret: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect();
+            self.secret_token = Some(secret);
+        }
+        self
+    }
+
+    fn secret_token(&self) -> anyhow::Result<&str> {
+        self.secret_token
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("webhook secret_token is required"))
+    }
+}
+
+struct State {
+    path: String,
+    secret_token: String,
+    tx: mpsc::Sender<Result<Update, Infallible>>,
+}
+
+/// Start the http listener.
+/// Returns the bound address and the update listener for dispatcher.
+pub async fn listen(
+    config: &WebhookConfig,
+) -> anyhow::Result<(SocketAddr, impl UpdateListener<Infallible>)> {
+    let url = Url::parse(&config.url)?;
+    let acceptor = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Some(tls_acceptor(cert, key)?),
+        (None, None) => None,
+        _ => return Err(anyhow::anyhow!("tls_cert and tls_key must be set together")),
+    };
+
+    let (tx, rx) = mpsc::channel(UPDATE_QUEUE_LEN);
+    let state = Arc::new(State {
+        path: url.path().to_string(),
+        secret_token: config.secret_token()?.to_string(),
+        tx,
+    });
+
+    let listener = TcpListener::bind(config.listen).await?;
+    let addr = listener.local_addr()?;
+    let (stop_token, stop_flag) = AsyncStopToken::new_pair();
+    tokio::spawn(serve(listener, acceptor, state, stop_flag));
+    tracing::info!("[webhook] listening on {addr}");
+
+    let stream = ReceiverStream::new(rx);
+    fn streamf<S, T>(state: &mut (S, T)) -> &mut S {
+        &mut state.0
+    }
+    let listener = StatefulListener::new(
+        (stream, stop_token),
+        streamf,
+        |state: &mut (_, AsyncStopToken)| state.1.clone(),
+    );
+    Ok((addr, listener))
+}
+
+/// Call setWebhook.
+/// It is done with raw request since secret token is not supported by teloxide yet.
+pub async fn set_webhook(
+    token: &str,
+    config: &WebhookConfig,
+    allowed_updates: &[AllowedUpdate],
+) -> anyhow::Result<()> {
+    let mut form = multipart::Form::new()
+        .text("url", config.url.clone())
+        .text("allowed_updates", serde_json::to_string(allowed_updates)?)
+        .text("secret_token", config.secret_token()?.to_string());
+    if let Some(cert) = &config.tls_cert {
+        let part = multipart::Part::bytes(std::fs::read(cert)?).file_name("cert.pem");
+        form = form.part("certificate", part);
+    }
+
+    let resp: serde_json::Value = reqwest::Client::new()
+        .post(format!("https://api.telegram.org/bot{token}/setWebhook"))
+        .multipart(form)
+        .send()
+        .await?
+        .json()
+        .await?;
+    if resp["ok"].as_bool() != Some(true) {
+        return Err(anyhow::anyhow!("set webhook failed: {resp}"));
+    }
+    tracing::info!("[webhook] webhook set to {}", config.url);
+    Ok(())
+}
+
+fn tls_acceptor(cert: &str, key: &str) -> anyhow::Result<TlsAcceptor> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(cert)?))?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    let key = rustls_pemfile::read_all(&mut BufReader::new(File::open(key)?))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::RSAKey(k)
+            | rustls_pemfile::Item::PKCS8Key(k)
+            | rustls_pemfile::Item::ECKey(k) => Some(PrivateKey(k)),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow::anyhow!("no private key found in {key}"))?;
+    let config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+async fn serve(
+    listener: TcpListener,
+    acceptor: Option<TlsAcceptor>,
+    state: Arc<State>,
+    mut stop_flag: AsyncStopFlag,
+) {
+    loop {
+        let stream = tokio::select! {
+            r = listener.accept() => match r {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::error!("[webhook] accept fail: {e}");
+                    continue;
+                }
+            },
+            _ = &mut stop_flag => break,
+        };
+
+        let state = state.clone();
+        let acceptor = acceptor.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| handle(state.clone(), req));
+            let r = match acceptor {
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => Http::new().serve_connection(stream, service).await,
+                    Err(e) => {
+                        tracing::debug!("[webhook] tls handshake fail: {e}");
+                        return;
+                    }
+                },
+                None => Http::new().serve_connection(stream, service).await,
+            };
+            if let Err(e) = r {
+                tracing::debug!("[webhook] serve connection fail: {e}");
+            }
+        });
+    }
+    tracing::info!("[webhook] listener stopped");
+}
+
+async fn handle(state: Arc<State>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let status = if req.uri().path() != state.path {
+        StatusCode::NOT_FOUND
+    } else if req.method() != Method::POST {
+        StatusCode::METHOD_NOT_ALLOWED
+    } else if !req
+        .headers()
+        .get(SECRET_TOKEN_HEADER)
+        .map(|v| constant_time_eq(v.as_bytes(), state.secret_token.as_bytes()))
+        .unwrap_or_default()
+    {
+        StatusCode::UNAUTHORIZED
+    } else {
+        match read_body(req.into_body(), MAX_UPDATE_SIZE).await {
+            Ok(Some(body)) => match serde_json::from_slice::<Update>(&body) {
+                Ok(update) => match state.tx.try_send(Ok(update)) {
+                    Ok(_) => StatusCode::OK,
+                    Err(_) => {
+                        tracing::warn!("[webhook] update queue is full");
+                        StatusCode::SERVICE_UNAVAILABLE
+                    }
+                },
+                Err(e) => {
+                    tracing::warn!("[webhook] invalid update: {e}");
+                    StatusCode::BAD_REQUEST
+                }
+            },
+            Ok(None) => StatusCode::PAYLOAD_TOO_LARGE,
+            Err(_) => StatusCode::BAD_REQUEST,
+        }
+    };
+
+    let mut resp = Response::new(Body::empty());
+    *resp.status_mut() = status;
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::dispatching::update_listeners::AsUpdateStream;
+    use tokio_stream::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn fake_update() {
+        let config = WebhookConfig {
+            url: "https://example.com/telegram".to_string(),
+            listen: SocketAddr::from(([127, 0, 0, 1], 0)),
+            secret_token: Some("secret".to_string()),
+            tls_cert: None,
+            tls_key: None,
+        };
+        let (addr, mut listener) = listen(&config).await.unwrap();
+        let endpoint = format!("http://{addr}/telegram");
+        let update = r#"{"update_id":1,"message":{"message_id":2,"date":1650000000,"chat":{"id":3,"type":"private","first_name":"a"},"from":{"id":3,"is_bot":false,"first_name":"a"},"text":"hello"}}"#;
+        let client = reqwest::Client::new();
+
+        let status = |r: reqwest::Response| r.status().as_u16();
+        let r = client.post(&endpoint).body(update).send().await.unwrap();
+        assert_eq!(status(r), 401);
+        let r = client
+            .post(format!("http://{addr}/other"))
+            .header(SECRET_TOKEN_HEADER, "secret")
+            .body(update)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(status(r), 404);
+        let r = client
+            .post(&endpoint)
+            .header(SECRET_TOKEN_HEADER, "secreT")
+            .body(update)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(status(r), 401);
+        let r = client
+            .post(&endpoint)
+            .header(SECRET_TOKEN_HEADER, "secret")
+            .body(vec![b' '; MAX_UPDATE_SIZE + 1])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(status(r), 413);
+        let r = client
+            .post(&endpoint)
+            .header(SECRET_TOKEN_HEADER, "secret")
+            .body(update)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(status(r), 200);
+
+        let stream = listener.as_stream();
+        tokio::pin!(stream);
+        let received = stream.next().await.unwrap().unwrap();
+        assert_eq!(received.id, 1);
+    }
+
+    #[test]
+    fn secret() {
+        let config = WebhookConfig {
+            url: "https://example.com/telegram".to_string(),
+            listen: default_listen(),
+            secret_token: None,
+            tls_cert: None,
+            tls_key: None,
+        };
+        assert!(config.secret_token().is_err());
+        let config = config.with_secret_token();
+        assert_eq!(config.secret_token().unwrap().len(), 32);
+    }
+}
@@ -0,0 +1,266 @@
+//! Command line tool to sync and search galleries without Telegram.
+//! It reads the same config file as the bot. Logs and progress are written to
+//! stderr, so the output can be piped.
+use clap::{Parser, Subcommand};
+use eh2telegraph::{
+    archive::CbzWriter,
+    collector::Registry,
+    config,
+    http_proxy::ProxiedClient,
+    searcher::{aggregate::AggregatedSearcher, local::PHashIndex, ImageSearcher},
+    stats::Stats,
+    storage,
+    sync::Synchronizer,
+    telegraph::Telegraph,
+};
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = "eh2telegraph command line tool")]
+struct Args {
+    #[clap(short, long, help = "Config file path")]
+    config: Option<String>,
+    #[clap(long, help = "Print results as JSON lines")]
+    json: bool,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Sync galleries to telegraph.
+    Sync {
+        urls: Vec<String>,
+        #[clap(long, help = "Read urls from the file, one per line")]
+        from_file: Option<String>,
+        #[clap(long, help = "Sync again, bypassing cache")]
+        force: bool,
+    },
+    /// Export the gallery as a CBZ archive.
+    Export {
+        url: String,
+        #[clap(short, long, help = "Output file path")]
+        output: String,
+    },
+    /// Search the image file.
+    Search { image: String },
+    /// Get or delete the cache of galleries.
+    Cache {
+        #[clap(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    Get { url: String },
+    Del { url: String },
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct BaseConfig {
+    telegraph: TelegraphConfig,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct TelegraphConfig {
+    tokens: Vec<String>,
+    author_name: Option<String>,
+    author_url: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct SyncOutput<'a> {
+    url: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    telegraph: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+    config::init(args.config.clone());
+
+    match run(&args).await {
+        Ok(true) => (),
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Returns false if any of the tasks failed.
+async fn run(args: &Args) -> anyhow::Result<bool> {
+    match &args.command {
+        Command::Sync {
+            urls,
+            from_file,
+            force,
+        } => {
+            let mut urls = urls.clone();
+            if let Some(path) = from_file {
+                urls.extend(parse_list(&tokio::fs::read_to_string(path).await?));
+            }
+            if urls.is_empty() {
+                return Err(anyhow::anyhow!("no url is given"));
+            }
+            let synchronizer = build_synchronizer()?;
+            let succeeded = sync(&synchronizer, &urls, *force, args.json).await?;
+            synchronizer.flush_stats().await?;
+            Ok(succeeded)
+        }
+        Command::Export { url, output } => {
+            let writer = CbzWriter::new(std::fs::File::create(output)?);
+            let (meta, _) = build_synchronizer()?.export_url(url, writer).await?;
+            if args.json {
+                let output = serde_json::json!({ "url": url, "title": meta.name, "file": output });
+                println!("{output}");
+            } else {
+                println!("{url} {output}");
+            }
+            Ok(true)
+        }
+        Command::Search { image } => {
+            let data = tokio::fs::read(image).await?;
+            let hits = AggregatedSearcher::new_from_config().search(data).await?;
+            if hits.is_empty() && !args.json {
+                println!("No hits found");
+            }
+            for hit in hits.iter() {
+                if args.json {
+                    println!("{}", serde_json::to_string(hit)?);
+                } else {
+                    println!(
+                        "{:>3} {:<8} {} {}",
+                        hit.score, hit.engine, hit.url, hit.name
+                    );
+                }
+            }
+            Ok(true)
+        }
+        Command::Cache { command } => {
+            let synchronizer = build_synchronizer()?;
+            match command {
+                CacheCommand::Get { url } => {
+                    let record = synchronizer.get_record_url(url).await?;
+                    let found = record.is_some();
+                    if args.json {
+                        println!("{}", serde_json::to_string(&record)?);
+                    } else {
+                        match record {
+                            Some(r) => println!("{}\n{}\n{}", r.url, r.title, r.link),
+                            None => println!("Not cached"),
+                        }
+                    }
+                    Ok(found)
+                }
+                CacheCommand::Del { url } => {
+                    synchronizer.purge_url(url).await?;
+                    if !args.json {
+                        println!("Deleted");
+                    }
+                    Ok(true)
+                }
+            }
+        }
+    }
+}
+
+async fn sync<C>(
+    synchronizer: &Synchronizer<C>,
+    urls: &[String],
+    force: bool,
+    json: bool,
+) -> anyhow::Result<bool>
+where
+    C: storage::KVStorage<String>,
+{
+    let mut failed = 0;
+    for (idx, url) in urls.iter().enumerate() {
+        eprintln!("[{}/{}] syncing {url}", idx + 1, urls.len());
+        let (telegraph, error) = match synchronizer.sync_url(url, force).await {
+            Ok(synced) => (Some(synced.url), None),
+            Err(e) => {
+                failed += 1;
+                (None, Some(e.to_string()))
+            }
+        };
+        if json {
+            let output = SyncOutput {
+                url,
+                telegraph,
+                error,
+            };
+            println!("{}", serde_json::to_string(&output)?);
+        } else {
+            match (telegraph, error) {
+                (Some(page), _) => println!("{url} {page}"),
+                (_, error) => println!("{url} failed: {}", error.unwrap_or_default()),
+            }
+        }
+    }
+    if urls.len() > 1 {
+        eprintln!(
+            "finished: {} succeeded, {failed} failed",
+            urls.len() - failed
+        );
+    }
+    Ok(failed == 0)
+}
+
+#[cfg(debug_assertions)]
+type Cache = storage::SimpleMemStorage;
+#[cfg(not(debug_assertions))]
+type Cache = storage::cloudflare_kv::CFStorage;
+
+fn build_synchronizer() -> anyhow::Result<Synchronizer<Cache>> {
+    let base_config: BaseConfig =
+        config::parse("base")?.ok_or_else(|| anyhow::anyhow!("base config can not be empty"))?;
+    let telegraph_config = base_config.telegraph;
+    let telegraph =
+        Telegraph::new(telegraph_config.tokens).with_proxy(ProxiedClient::new_from_config());
+    #[cfg(debug_assertions)]
+    let cache = Cache::default();
+    #[cfg(not(debug_assertions))]
+    let cache = Cache::new_from_config()?;
+
+    // hashes and counters are saved to the same storage as the bot, so the bot can use them
+    let mut synchronizer = Synchronizer::new(telegraph, Registry::new_from_config(), cache.clone())
+        .with_phash_index(PHashIndex::new(cache))
+        .with_stats(std::sync::Arc::new(Stats::default()));
+    if telegraph_config.author_name.is_some() {
+        synchronizer =
+            synchronizer.with_author(telegraph_config.author_name, telegraph_config.author_url);
+    }
+    Ok(synchronizer)
+}
+
+/// Urls in the list file. Empty lines and lines starting with `#` are skipped.
+fn parse_list(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(ToString::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list() {
+        let content = "# galleries\nhttps://e-hentai.org/g/1/a/\n\n  https://nhentai.net/g/2 \n";
+        assert_eq!(
+            parse_list(content),
+            vec!["https://e-hentai.org/g/1/a/", "https://nhentai.net/g/2"]
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! Export galleries as CBZ archives with `ComicInfo.xml`.
+//! Images are written into the archive once downloaded, so only the images
+//! being downloaded are kept in memory.
+use std::io::{Seek, Write};
+
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    collector::{AlbumMeta, ImageData, ImageMeta},
+    stream::{AsyncStream, Buffered},
+    sync::ERR_THRESHOLD,
+};
+
+pub const COMIC_INFO: &str = "ComicInfo.xml";
+
+// local header and central directory record of an entry, with the file name
+const ENTRY_OVERHEAD: u64 = 128;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ArchiveError {
+    #[error("archive is larger than {0} bytes")]
+    TooLarge(u64),
+    #[error("zip error {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("io error {0}")]
+    Io(#[from] std::io::Error),
+    #[error("blocking task error {0}")]
+    Join(#[from] tokio::task::JoinError),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ExportError<SE> {
+    #[error("stream error {0}")]
+    Stream(SE),
+    #[error(transparent)]
+    Archive(#[from] ArchiveError),
+}
+
+/// CBZ writer. Images are named by their order and stored without compression.
+pub struct CbzWriter<W: Write + Seek> {
+    zip: ZipWriter<W>,
+    pages: usize,
+    size: u64,
+    limit: Option<u64>,
+}
+
+impl<W: Write + Seek> CbzWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            zip: ZipWriter::new(inner),
+            pages: 0,
+            size: 0,
+            limit: None,
+        }
+    }
+
+    /// Fail with `ArchiveError::TooLarge` before the archive exceeds the limit.
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn pages(&self) -> usize {
+        self.pages
+    }
+
+    /// Estimated size of the archive.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn add_image(&mut self, meta: &ImageMeta, data: &[u8]) -> Result<(), ArchiveError> {
+        self.reserve(data.len() as u64)?;
+        self.pages += 1;
+        let name = format!("{:04}.{}", self.pages, extension(meta, data));
+        self.zip.start_file(name, options())?;
+        self.zip.write_all(data)?;
+        Ok(())
+    }
+
+    /// Write `ComicInfo.xml` and finish the archive.
+    pub fn finish(mut self, meta: &AlbumMeta) -> Result<W, ArchiveError> {
+        let info = comic_info(meta, self.pages);
+        self.reserve(info.len() as u64)?;
+        self.zip.start_file(COMIC_INFO, options())?;
+        self.zip.write_all(info.as_bytes())?;
+        Ok(self.zip.finish()?)
+    }
+
+    fn reserve(&mut self, len: u64) -> Result<(), ArchiveError> {
+        let size = self.size + len + ENTRY_OVERHEAD;
+        if let Some(limit) = self.limit {
+            if size > limit {
+                return Err(ArchiveError::TooLarge(limit));
+            }
+        }
+        self.size = size;
+        Ok(())
+    }
+}
+
+/// Download images of the stream in order and write them into the archive.
+/// Writing is done in blocking threads since the writer is usually a file.
+pub async fn export_stream<S, SE, W>(
+    meta: &AlbumMeta,
+    stream: S,
+    writer: CbzWriter<W>,
+    concurrent: usize,
+) -> Result<W, ExportError<SE>>
+where
+    S: AsyncStream<Item = Result<(ImageMeta, ImageData), SE>>,
+    S::Future: Send + 'static,
+    SE: Send + 'static,
+    W: Write + Seek + Send + 'static,
+{
+    let mut stream = Buffered::new(stream, concurrent);
+    let mut writer = writer;
+    let mut err_count = 0;
+    while let Some(fut) = stream.next() {
+        let (image, data) = match fut.await {
+            Err(e) => {
+                err_count += 1;
+                if err_count > ERR_THRESHOLD {
+                    return Err(ExportError::Stream(e));
+                }
+                continue;
+            }
+            Ok(d) => {
+                err_count = 0;
+                d
+            }
+        };
+        writer = blocking(move || {
+            writer.add_image(&image, &data)?;
+            Ok(writer)
+        })
+        .await?;
+    }
+    let info = meta.clone();
+    let inner = blocking(move || writer.finish(&info)).await?;
+    tracing::info!("[archive] export success for {}", meta.link);
+    Ok(inner)
+}
+
+async fn blocking<T, F>(f: F) -> Result<T, ArchiveError>
+where
+    F: FnOnce() -> Result<T, ArchiveError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await?
+}
+
+#[inline]
+fn options() -> FileOptions {
+    // images are compressed already
+    FileOptions::default().compression_method(CompressionMethod::Stored)
+}
+
+/// Extension by the image content, or by the url if it is unknown.
+fn extension(meta: &ImageMeta, data: &[u8]) -> String {
+    if let Some(ext) = image::guess_format(data)
+        .ok()
+        .and_then(|f| f.extensions_str().first())
+    {
+        return ext.to_string();
+    }
+    meta.url
+        .rsplit_once('.')
+        .map(|(_, ext)| ext)
+        .filter(|ext| !ext.is_empty() && ext.len() <= 4 && ext.chars().all(char::is_alphanumeric))
+        .unwrap_or("jpg")
+        .to_lowercase()
+}
+
+/// `ComicInfo.xml` of the gallery, in ComicRack's format.
+pub fn comic_info(meta: &AlbumMeta, pages: usize) -> String {
+    let mut fields = vec![("Title", meta.name.clone())];
+    if let Some(description) = &meta.description {
+        fields.push(("Summary", description.clone()));
+    }
+    if let Some(authors) = meta.authors.as_ref().filter(|a| !a.is_empty()) {
+        fields.push(("Writer", authors.join(", ")));
+    }
+    if let Some(class) = &meta.class {
+        fields.push(("Genre", class.clone()));
+    }
+    if let Some(tags) = meta.tags.as_ref().filter(|t| !t.is_empty()) {
+        fields.push(("Tags", tags.join(", ")));
+    }
+    fields.push(("Web", meta.link.clone()));
+    fields.push(("PageCount", pages.to_string()));
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<ComicInfo xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n",
+    );
+    for (name, value) in fields {
+        xml.push_str(&format!("  <{name}>{}</{name}>\n", escape_xml(&value)));
+    }
+    xml.push_str("</ComicInfo>\n");
+    xml
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::*;
+
+    fn album() -> AlbumMeta {
+        AlbumMeta {
+            link: "https://e-hentai.org/g/1/a/".to_string(),
+            name: "Tom & Jerry <1>".to_string(),
+            class: Some("Manga".to_string()),
+            description: None,
+            authors: Some(vec!["tom".to_string(), "jerry".to_string()]),
+            tags: Some(vec!["language:english".to_string()]),
+        }
+    }
+
+    fn image(url: &str) -> ImageMeta {
+        ImageMeta {
+            id: String::new(),
+            url: url.to_string(),
+            description: None,
+        }
+    }
+
+    #[test]
+    fn info() {
+        let xml = comic_info(&album(), 2);
+        assert!(xml.contains("<Title>Tom &amp; Jerry &lt;1&gt;</Title>"));
+        assert!(xml.contains("<Writer>tom, jerry</Writer>"));
+        assert!(xml.contains("<Tags>language:english</Tags>"));
+        assert!(xml.contains("<Web>https://e-hentai.org/g/1/a/</Web>"));
+        assert!(xml.contains("<PageCount>2</PageCount>"));
+        assert!(!xml.contains("<Summary>"));
+    }
+
+    #[test]
+    fn write() {
+        let png = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0];
+        let mut writer = CbzWriter::new(Cursor::new(Vec::new()));
+        writer
+            .add_image(&image("https://example.com/a.png"), &png)
+            .unwrap();
+        writer
+            .add_image(&image("https://example.com/b.WEBP"), b"unknown")
+            .unwrap();
+        let inner = writer.finish(&album()).unwrap();
+
+        let mut zip = zip::ZipArchive::new(inner).unwrap();
+        let names: Vec<_> = zip.file_names().collect();
+        assert_eq!(names.len(), 3);
+        assert!(zip.by_name("0001.png").is_ok());
+        assert!(zip.by_name("0002.webp").is_ok());
+        let mut info = String::new();
+        zip.by_name(COMIC_INFO)
+            .unwrap()
+            .read_to_string(&mut info)
+            .unwrap();
+        assert!(info.contains("<PageCount>2</PageCount>"));
+    }
+
+    #[test]
+    fn limit() {
+        let mut writer = CbzWriter::new(Cursor::new(Vec::new())).with_limit(1024);
+        let meta = image("https://example.com/a.jpg");
+        writer.add_image(&meta, &[0; 512]).unwrap();
+        assert!(matches!(
+            writer.add_image(&meta, &[0; 512]),
+            Err(ArchiveError::TooLarge(1024))
+        ));
+    }
+}
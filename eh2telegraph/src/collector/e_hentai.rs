@@ -15,7 +15,7 @@ use std::time::Duration;
 
 use super::{
     utils::paged::{PageFormatter, PageIndicator, Paged},
-    AlbumMeta, Collector, ImageData, ImageMeta,
+    AlbumMeta, Collector, ImageData, ImageMeta, InputError,
 };
 
 lazy_static::lazy_static! {
@@ -91,7 +91,7 @@ impl Collector for EHCollector {
             let (album_id, album_token) = match (g, album_id, album_token) {
                 (Some("g"), Some(album_id), Some(album_token)) => (album_id, album_token),
                 _ => {
-                    return Err(anyhow::anyhow!("invalid input path({path}), gallery url is expected(like https://e-hentai.org/g/2127986/da1deffea5)"));
+                    return Err(InputError(format!("invalid input path({path}), gallery url is expected(like https://e-hentai.org/g/2127986/da1deffea5)")).into());
                 }
             };
             let url = format!("https://e-hentai.org/g/{album_id}/{album_token}");
@@ -116,9 +116,10 @@ impl Collector for EHCollector {
             }
 
             if image_page_links.is_empty() {
-                return Err(anyhow::anyhow!(
-                    "invalid url, maybe resource has been deleted."
-                ));
+                return Err(InputError(
+                    "invalid url, maybe resource has been deleted.".to_string(),
+                )
+                .into());
             }
 
             Ok((
@@ -16,7 +16,7 @@ use crate::{
 
 use super::{
     utils::paged::{PageFormatter, PageIndicator, Paged},
-    AlbumMeta, Collector, ImageData, ImageMeta,
+    AlbumMeta, Collector, ImageData, ImageMeta, InputError,
 };
 
 lazy_static::lazy_static! {
@@ -103,7 +103,7 @@ impl Collector for EXCollector {
             let (album_id, album_token) = match (g, album_id, album_token) {
                 (Some("g"), Some(album_id), Some(album_token)) => (album_id, album_token),
                 _ => {
-                    return Err(anyhow::anyhow!("invalid input path({path}), gallery url is expected(like https://exhentai.org/g/2129939/01a6e086b9)"));
+                    return Err(InputError(format!("invalid input path({path}), gallery url is expected(like https://exhentai.org/g/2129939/01a6e086b9)")).into());
                 }
             };
             let url = format!("https://exhentai.org/g/{album_id}/{album_token}");
@@ -33,6 +33,12 @@ pub struct AlbumMeta {
     pub tags: Option<Vec<String>>,
 }
 
+/// Error caused by the requested url, like invalid urls or deleted galleries.
+/// It is expected and should not be reported as a failure of the service.
+#[derive(thiserror::Error, Debug)]
+#[error("{0}")]
+pub struct InputError(pub String);
+
 /// Generic collector.
 /// The `async fetch` returns the result of `AlbumMeta` and `ImageStream`.
 /// By exposing `ImageStream`, we can fetch the images lazily. For low
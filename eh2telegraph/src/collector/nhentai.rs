@@ -8,12 +8,13 @@ use std::time::Duration;
 
 use crate::{
     http_client::{GhostClient, GhostClientBuilder},
+    metrics::ObserveExt,
     stream::AsyncStream,
     util::get_bytes,
     util::match_first_group,
 };
 
-use super::{AlbumMeta, Collector, ImageData, ImageMeta};
+use super::{AlbumMeta, Collector, ImageData, ImageMeta, InputError};
 
 lazy_static::lazy_static! {
     static ref TITLE_RE: Regex = Regex::new(r#"<span class="pretty">(.*?)</span>"#).unwrap();
@@ -82,7 +83,7 @@ impl Collector for NHCollector {
             let album_id = match (g, album_id) {
                 (Some("g"), Some(album_id)) => album_id,
                 _ => {
-                    return Err(anyhow::anyhow!("invalid input path({path}), gallery url is expected(like https://nhentai.net/g/333678)"));
+                    return Err(InputError(format!("invalid input path({path}), gallery url is expected(like https://nhentai.net/g/333678)")).into());
                 }
             };
             let url = format!("https://nhentai.net/g/{album_id}");
@@ -94,6 +95,7 @@ impl Collector for NHCollector {
                 .get(&url)
                 .send()
                 .await
+                .observe()
                 .and_then(Response::error_for_status)?
                 .text()
                 .await?;
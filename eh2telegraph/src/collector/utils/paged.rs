@@ -1,6 +1,6 @@
 use reqwest::Response;
 
-use crate::http_proxy::HttpRequestBuilder;
+use crate::{http_proxy::HttpRequestBuilder, metrics::ObserveExt};
 
 pub trait PageFormatter {
     fn format_n(&self, n: usize) -> String;
@@ -44,6 +44,7 @@ where
             .get_builder(&url)
             .send()
             .await
+            .observe()
             .and_then(Response::error_for_status)?
             .text()
             .await?;
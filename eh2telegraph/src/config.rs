@@ -43,3 +43,17 @@ where
         .map(|v| serde_yaml::from_value(v))
         .transpose()
 }
+
+/// Parse struct from another config file, like the config of another deployment.
+pub fn parse_file<T>(path: &str, key: &str) -> anyhow::Result<Option<T>>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let file_content = std::fs::read_to_string(path)?;
+    let mut mapping: HashMap<String, serde_yaml::Value> = serde_yaml::from_str(&file_content)?;
+    mapping
+        .remove(key)
+        .map(|v| serde_yaml::from_value(v))
+        .transpose()
+        .map_err(Into::into)
+}
@@ -1,13 +1,16 @@
 #![feature(generic_associated_types)]
 #![feature(type_alias_impl_trait)]
 
+pub mod archive;
 pub mod buffer;
 pub mod collector;
 pub mod config;
 pub mod http_client;
 pub mod http_proxy;
 pub mod indexer;
+pub mod metrics;
 pub mod searcher;
+pub mod stats;
 pub mod storage;
 pub mod stream;
 pub mod sync;
@@ -0,0 +1,172 @@
+//! Prometheus metrics.
+//! Metrics are registered to the default registry, and exported by `encode`.
+use std::net::IpAddr;
+
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    Encoder, Histogram, HistogramVec, IntCounterVec, IntGauge, TextEncoder,
+};
+use reqwest::Response;
+
+pub static SYNC_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "eh2telegraph_sync_duration_seconds",
+        "Duration of syncing galleries, cache hits excluded.",
+        &["collector", "result"],
+        vec![1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0]
+    )
+    .expect("unable to register metric")
+});
+
+pub static SYNC_IN_FLIGHT: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!("eh2telegraph_sync_in_flight", "Galleries being synced.")
+        .expect("unable to register metric")
+});
+
+pub static BUFFERED_QUEUE: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "eh2telegraph_buffered_queue_depth",
+        "Images being downloaded in buffered streams."
+    )
+    .expect("unable to register metric")
+});
+
+pub static HTTP_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "eh2telegraph_http_requests_total",
+        "Outgoing http requests by site.",
+        &["host"]
+    )
+    .expect("unable to register metric")
+});
+
+pub static HTTP_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "eh2telegraph_http_errors_total",
+        "Outgoing http requests failed or with error status by site.",
+        &["host"]
+    )
+    .expect("unable to register metric")
+});
+
+pub static TELEGRAPH_UPLOAD_DURATION: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "eh2telegraph_telegraph_upload_duration_seconds",
+        "Duration of uploading a batch of images to telegraph.",
+        vec![0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 40.0]
+    )
+    .expect("unable to register metric")
+});
+
+/// Increase the gauge until the guard is dropped.
+pub struct GaugeGuard<'a>(&'a IntGauge);
+
+impl<'a> GaugeGuard<'a> {
+    pub fn new(gauge: &'a IntGauge) -> Self {
+        gauge.inc();
+        Self(gauge)
+    }
+}
+
+impl<'a> Drop for GaugeGuard<'a> {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// Label of the host, so the number of series is bounded.
+/// Images of e-hentai are served by H@H nodes, which are usually ip addresses.
+fn site(host: &str) -> &'static str {
+    let is = |domain: &str| {
+        host == domain
+            || host
+                .strip_suffix(domain)
+                .map(|h| h.ends_with('.'))
+                .unwrap_or_default()
+    };
+    if is("e-hentai.org") {
+        "e-hentai"
+    } else if is("exhentai.org") {
+        "exhentai"
+    } else if is("nhentai.net") || is("nhentai.to") {
+        "nhentai"
+    } else if is("telegra.ph") {
+        "telegraph"
+    } else if is("saucenao.com") {
+        "saucenao"
+    } else if is("hath.network") || is("ehgt.org") || host.parse::<IpAddr>().is_ok() {
+        "image-node"
+    } else {
+        "other"
+    }
+}
+
+/// Count responses by site.
+pub trait ObserveExt {
+    fn observe(self) -> Self;
+}
+
+impl ObserveExt for reqwest::Result<Response> {
+    fn observe(self) -> Self {
+        let (url, failed) = match &self {
+            Ok(r) => (Some(r.url()), !r.status().is_success()),
+            Err(e) => (e.url(), true),
+        };
+        let site = site(url.and_then(|u| u.host_str()).unwrap_or_default());
+        HTTP_REQUESTS.with_label_values(&[site]).inc();
+        if failed {
+            HTTP_ERRORS.with_label_values(&[site]).inc();
+        }
+        self
+    }
+}
+
+/// All metrics in prometheus text format.
+pub fn encode() -> String {
+    let mut buf = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buf)
+        .expect("unable to encode metrics");
+    String::from_utf8(buf).expect("metrics are not utf8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export() {
+        // metrics are global and may be changed by other tests, so only names are checked
+        Lazy::force(&SYNC_IN_FLIGHT);
+        HTTP_REQUESTS.with_label_values(&["telegraph"]).inc();
+        let text = encode();
+        assert!(text.contains("eh2telegraph_sync_in_flight "));
+        assert!(text.contains("eh2telegraph_http_requests_total{host=\"telegraph\"} "));
+    }
+
+    #[test]
+    fn guard() {
+        let gauge = IntGauge::new("test_gauge", "test").unwrap();
+        {
+            let _guard = GaugeGuard::new(&gauge);
+            assert_eq!(gauge.get(), 1);
+        }
+        assert_eq!(gauge.get(), 0);
+    }
+
+    #[test]
+    fn sites() {
+        assert_eq!(site("e-hentai.org"), "e-hentai");
+        assert_eq!(site("api.e-hentai.org"), "e-hentai");
+        assert_eq!(site("fake-e-hentai.org"), "other");
+        assert_eq!(site("s.exhentai.org"), "exhentai");
+        assert_eq!(site("i.nhentai.net"), "nhentai");
+        assert_eq!(site("api.telegra.ph"), "telegraph");
+        assert_eq!(site("saucenao.com"), "saucenao");
+        assert_eq!(site("abcd.efgh.hath.network"), "image-node");
+        assert_eq!(site("1.2.3.4"), "image-node");
+        assert_eq!(site("example.com"), "other");
+        assert_eq!(site(""), "other");
+    }
+}
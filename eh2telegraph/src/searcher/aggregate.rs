@@ -0,0 +1,462 @@
+//! Aggregated searcher.
+//! It queries multiple engines concurrently or in fallback order, normalizes
+//! their scores, dedupes and ranks the hits.
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+use futures::{future::BoxFuture, Future};
+use serde::Deserialize;
+
+use crate::{
+    config,
+    stats::Stats,
+    storage::{KVStorage, SimpleMemStorage},
+};
+
+use super::{
+    ascii2d::Ascii2dSearcher, file_hash::FileHashSearcher, iqdb::IqdbSearcher,
+    local::LocalSearcher, saucenao::SaucenaoSearcher, HitId, ImageSearcher, SearchHit,
+};
+
+const CONFIG_KEY: &str = "search";
+// ascii2d has no similarity and almost always returns something, so its hits
+// are kept below the default thresholds and only useful with a lower min_score
+const ASCII2D_SCALE: Scale = Scale::Rank { top: 45, step: 5 };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Query engines one by one, and stop at the first engine with hits.
+    #[default]
+    Fallback,
+    /// Query all engines at the same time.
+    Concurrent,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchConfig {
+    #[serde(default = "default_engines")]
+    pub engines: Vec<String>,
+    #[serde(default)]
+    pub mode: Mode,
+    #[serde(default = "default_min_score")]
+    pub min_score: u8,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            engines: default_engines(),
+            mode: Mode::default(),
+            min_score: default_min_score(),
+        }
+    }
+}
+
+fn default_engines() -> Vec<String> {
+    vec![
+        "local".to_string(),
+        "ehash".to_string(),
+        "saucenao".to_string(),
+        "iqdb".to_string(),
+        "ascii2d".to_string(),
+    ]
+}
+
+fn default_min_score() -> u8 {
+    50
+}
+
+/// How the similarity of an engine is normalized to score.
+/// Score uses the same scale as saucenao similarity.
+#[derive(Debug, Clone, Copy)]
+pub enum Scale {
+    /// Similarity is used as is.
+    Identity,
+    /// Similarity in [floor, 100] is mapped to [0, 100].
+    Linear { floor: u8 },
+    /// For engines without similarity, score is given by rank.
+    Rank { top: u8, step: u8 },
+}
+
+impl Scale {
+    fn score(&self, similarity: Option<u8>, rank: usize) -> u8 {
+        match (*self, similarity) {
+            (Self::Identity, Some(s)) => s,
+            (Self::Linear { floor }, Some(s)) => {
+                (s.saturating_sub(floor) as u32 * 100 / (100 - floor.min(99)) as u32).min(100) as u8
+            }
+            (Self::Rank { top, step }, _) => {
+                top.saturating_sub((rank.min(u8::MAX as usize) as u8).saturating_mul(step))
+            }
+            (_, None) => 0,
+        }
+    }
+}
+
+type HitFuture = BoxFuture<'static, anyhow::Result<Vec<SearchHit>>>;
+
+trait DynSearcher: Send + Sync {
+    fn search_hits(&self, data: Vec<u8>) -> HitFuture;
+}
+
+impl<S> DynSearcher for S
+where
+    S: ImageSearcher + Send + Sync,
+    S::FetchFuture<Vec<u8>>: Send + 'static,
+    S::SeacheError: Into<anyhow::Error>,
+    S::SearchOutput: IntoIterator,
+    <S::SearchOutput as IntoIterator>::Item: Into<SearchHit>,
+{
+    fn search_hits(&self, data: Vec<u8>) -> HitFuture {
+        let fut = self.search(data);
+        Box::pin(async move {
+            let output = fut.await.map_err(Into::into)?;
+            Ok(output.into_iter().map(Into::into).collect())
+        })
+    }
+}
+
+struct Engine {
+    name: &'static str,
+    scale: Scale,
+    searcher: Box<dyn DynSearcher>,
+}
+
+impl Engine {
+    async fn search(&self, data: Vec<u8>) -> anyhow::Result<Vec<SearchHit>> {
+        let mut hits = self.searcher.search_hits(data).await?;
+        for (rank, hit) in hits.iter_mut().enumerate() {
+            hit.engine = self.name;
+            hit.score = self.scale.score(hit.similarity, rank);
+        }
+        tracing::debug!(
+            "[aggregate] engine {} returns {} hits",
+            self.name,
+            hits.len()
+        );
+        Ok(hits)
+    }
+}
+
+/// Searcher which aggregates multiple engines.
+#[derive(Clone)]
+pub struct AggregatedSearcher {
+    engines: Vec<Arc<Engine>>,
+    mode: Mode,
+    min_score: u8,
+    stats: Option<Arc<Stats>>,
+}
+
+impl std::fmt::Debug for AggregatedSearcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AggregatedSearcher")
+            .field(
+                "engines",
+                &self.engines.iter().map(|e| e.name).collect::<Vec<_>>(),
+            )
+            .field("mode", &self.mode)
+            .field("min_score", &self.min_score)
+            .finish()
+    }
+}
+
+impl AggregatedSearcher {
+    pub fn new(mode: Mode) -> Self {
+        Self {
+            engines: Vec::new(),
+            mode,
+            min_score: default_min_score(),
+            stats: None,
+        }
+    }
+
+    /// Build remote engines from config. The local engine is skipped.
+    pub fn new_from_config() -> Self {
+        Self::build_from_config(None::<LocalSearcher<SimpleMemStorage>>)
+    }
+
+    /// Build engines from config, with the local engine placed where
+    /// `local` is listed.
+    pub fn new_from_config_with_local<S>(local: LocalSearcher<S>) -> Self
+    where
+        S: KVStorage<String> + Clone + Send + Sync + 'static,
+    {
+        Self::build_from_config(Some(local))
+    }
+
+    fn build_from_config<S>(mut local: Option<LocalSearcher<S>>) -> Self
+    where
+        S: KVStorage<String> + Clone + Send + Sync + 'static,
+    {
+        let config: SearchConfig = config::parse(CONFIG_KEY)
+            .expect("unable to parse search config")
+            .unwrap_or_default();
+        let mut searcher = Self::new(config.mode).with_min_score(config.min_score);
+        for engine in config.engines.iter() {
+            searcher = match engine.as_str() {
+                "local" => match local.take() {
+                    Some(local) => searcher.with_engine("local", Scale::Identity, local),
+                    None => {
+                        tracing::warn!("[aggregate] local engine is not available, skipped");
+                        searcher
+                    }
+                },
+                "ehash" => searcher.with_engine(
+                    "ehash",
+                    Scale::Identity,
+                    FileHashSearcher::new_from_config(),
+                ),
+                "saucenao" => searcher.with_engine(
+                    "saucenao",
+                    Scale::Identity,
+                    SaucenaoSearcher::new_from_config(),
+                ),
+                "iqdb" => {
+                    searcher.with_engine("iqdb", Scale::Linear { floor: 50 }, IqdbSearcher::new())
+                }
+                "ascii2d" => searcher.with_engine(
+                    "ascii2d",
+                    ASCII2D_SCALE,
+                    Ascii2dSearcher::new_from_config(),
+                ),
+                _ => panic!("unknown search engine {engine}"),
+            };
+        }
+        searcher
+    }
+
+    /// Add an engine. Engines are queried in the order of adding in fallback mode.
+    pub fn with_engine<S>(mut self, name: &'static str, scale: Scale, searcher: S) -> Self
+    where
+        S: ImageSearcher + Send + Sync + 'static,
+        S::FetchFuture<Vec<u8>>: Send + 'static,
+        S::SeacheError: Into<anyhow::Error>,
+        S::SearchOutput: IntoIterator,
+        <S::SearchOutput as IntoIterator>::Item: Into<SearchHit>,
+    {
+        self.engines.push(Arc::new(Engine {
+            name,
+            scale,
+            searcher: Box::new(searcher),
+        }));
+        self
+    }
+
+    /// Hits with lower score will be dropped.
+    pub fn with_min_score(mut self, min_score: u8) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    /// Count hits of engines and failures.
+    pub fn with_stats(mut self, stats: Arc<Stats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    async fn search(
+        engines: &[Arc<Engine>],
+        mode: Mode,
+        min_score: u8,
+        stats: Option<&Stats>,
+        data: Vec<u8>,
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        let mut hits = Vec::new();
+        let mut last_err = None;
+        let mut succeeded = false;
+
+        match mode {
+            Mode::Concurrent => {
+                let results =
+                    futures::future::join_all(engines.iter().map(|e| e.search(data.clone()))).await;
+                for (engine, r) in engines.iter().zip(results) {
+                    match r {
+                        Ok(h) => {
+                            succeeded = true;
+                            hits.extend(h);
+                        }
+                        Err(e) => {
+                            tracing::warn!("[aggregate] engine {} failed: {e}", engine.name);
+                            last_err = Some(e);
+                        }
+                    }
+                }
+            }
+            Mode::Fallback => {
+                for engine in engines.iter() {
+                    match engine.search(data.clone()).await {
+                        Ok(h) => {
+                            succeeded = true;
+                            hits.extend(h.into_iter().filter(|x| x.score >= min_score));
+                            if !hits.is_empty() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            tracing::warn!("[aggregate] engine {} failed: {e}", engine.name);
+                            last_err = Some(e);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !succeeded {
+            if let Some(stats) = stats {
+                stats.incr("failure|search", 1);
+            }
+            return Err(last_err.unwrap_or_else(|| anyhow::anyhow!("no search engine available")));
+        }
+        hits.retain(|x| x.score >= min_score);
+        if let Some(stats) = stats {
+            for engine in engines.iter() {
+                if hits.iter().any(|h| h.engine == engine.name) {
+                    stats.incr(format!("search|{}", engine.name), 1);
+                }
+            }
+        }
+        Ok(rank(hits))
+    }
+}
+
+/// Dedupe hits which point to the same gallery or post, and sort them by score.
+fn rank(hits: Vec<SearchHit>) -> Vec<SearchHit> {
+    let mut deduped: HashMap<String, SearchHit> = HashMap::with_capacity(hits.len());
+    let mut order = Vec::with_capacity(hits.len());
+    for hit in hits {
+        let key = match &hit.id {
+            Some(HitId::FileHash(h)) => format!("file|{h}"),
+            Some(HitId::Id(id)) => format!("{:?}|{id}", hit.site),
+            None => hit.url.clone(),
+        };
+        match deduped.get_mut(&key) {
+            Some(existing) => {
+                // keep candidates found by either of them
+                if hit.score > existing.score {
+                    let candidates = std::mem::take(&mut existing.candidates);
+                    *existing = hit;
+                    if existing.candidates.is_empty() {
+                        existing.candidates = candidates;
+                    }
+                } else if existing.candidates.is_empty() {
+                    existing.candidates = hit.candidates;
+                }
+            }
+            None => {
+                order.push(key.clone());
+                deduped.insert(key, hit);
+            }
+        }
+    }
+    let mut ranked: Vec<_> = order
+        .into_iter()
+        .filter_map(|k| deduped.remove(&k))
+        .collect();
+    ranked.sort_by_key(|h| std::cmp::Reverse(h.score));
+    ranked
+}
+
+impl ImageSearcher for AggregatedSearcher {
+    type SeacheError = anyhow::Error;
+    type SearchOutput = Vec<SearchHit>;
+    type FetchFuture<T> = impl Future<Output = Result<Self::SearchOutput, Self::SeacheError>>;
+
+    fn search<T: Into<Cow<'static, [u8]>>>(&self, data: T) -> Self::FetchFuture<T> {
+        let data = data.into().into_owned();
+        let engines = self.engines.clone();
+        let mode = self.mode;
+        let min_score = self.min_score;
+        let stats = self.stats.clone();
+        async move { Self::search(&engines, mode, min_score, stats.as_deref(), data).await }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::searcher::{f_hash::GalleryCandidate, Site};
+
+    fn hit(site: Site, id: Option<HitId>, score: u8) -> SearchHit {
+        SearchHit {
+            engine: "test",
+            site,
+            url: format!("{site:?}"),
+            name: String::new(),
+            similarity: Some(score),
+            score,
+            id,
+            candidates: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn scale() {
+        assert_eq!(Scale::Identity.score(Some(80), 3), 80);
+        assert_eq!(Scale::Linear { floor: 50 }.score(Some(90), 0), 80);
+        assert_eq!(Scale::Linear { floor: 50 }.score(Some(40), 0), 0);
+        assert_eq!(Scale::Rank { top: 75, step: 5 }.score(None, 0), 75);
+        assert_eq!(Scale::Rank { top: 75, step: 5 }.score(None, 2), 65);
+        assert_eq!(Scale::Rank { top: 75, step: 5 }.score(None, 100), 0);
+    }
+
+    struct FixedSearcher(Vec<SearchHit>);
+
+    impl ImageSearcher for FixedSearcher {
+        type SeacheError = anyhow::Error;
+        type SearchOutput = Vec<SearchHit>;
+        type FetchFuture<T> = impl Future<Output = Result<Self::SearchOutput, Self::SeacheError>>;
+
+        fn search<T: Into<Cow<'static, [u8]>>>(&self, _data: T) -> Self::FetchFuture<T> {
+            let hits = self.0.clone();
+            async move { Ok(hits) }
+        }
+    }
+
+    #[tokio::test]
+    async fn ascii2d_below_threshold() {
+        let mut found = hit(Site::EHentai, Some(HitId::Id("1/a".to_string())), 0);
+        found.similarity = None;
+        let searcher = AggregatedSearcher::new(Mode::Fallback).with_engine(
+            "ascii2d",
+            ASCII2D_SCALE,
+            FixedSearcher(vec![found]),
+        );
+        let hits = searcher.search(vec![]).await.unwrap();
+        assert!(hits.is_empty());
+    }
+
+    #[test]
+    fn dedupe_and_rank() {
+        let hits = vec![
+            hit(Site::Pixiv, Some(HitId::Id("1".to_string())), 60),
+            hit(Site::NHentai, Some(HitId::Id("1".to_string())), 70),
+            hit(Site::Pixiv, Some(HitId::Id("1".to_string())), 90),
+            hit(Site::EHentai, Some(HitId::FileHash("a".to_string())), 80),
+        ];
+        let ranked = rank(hits);
+        assert_eq!(ranked.len(), 3);
+        assert_eq!((ranked[0].site, ranked[0].score), (Site::Pixiv, 90));
+        assert_eq!((ranked[1].site, ranked[1].score), (Site::EHentai, 80));
+        assert_eq!((ranked[2].site, ranked[2].score), (Site::NHentai, 70));
+    }
+
+    #[test]
+    fn keep_candidates() {
+        let candidate = GalleryCandidate {
+            url: "https://e-hentai.org/g/1/a".to_string(),
+            title: String::new(),
+            language: None,
+            pages: None,
+        };
+        let mut found = hit(Site::EHentai, Some(HitId::FileHash("a".to_string())), 80);
+        found.candidates = vec![candidate.clone()];
+        let hits = vec![
+            found,
+            hit(Site::EHentai, Some(HitId::FileHash("a".to_string())), 90),
+        ];
+        let ranked = rank(hits);
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].score, 90);
+        assert_eq!(ranked[0].candidates, vec![candidate]);
+    }
+}
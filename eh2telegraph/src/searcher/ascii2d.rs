@@ -0,0 +1,139 @@
+use std::borrow::Cow;
+
+use futures::Future;
+use ipnet::Ipv6Net;
+use regex::Regex;
+use reqwest::{
+    header,
+    multipart::{self, Part},
+    Response,
+};
+
+use crate::{http_client::GhostClient, metrics::ObserveExt, util::match_first_group};
+
+use super::{ImageSearcher, SearchHit};
+
+lazy_static::lazy_static! {
+    static ref CSRF_RE: Regex = Regex::new(r#"<meta name="csrf-token" content="(.*?)""#).unwrap();
+    static ref DETAIL_RE: Regex = Regex::new(r#"(?s)<div class=['"]detail-box gray-link['"]>\s*<h6>.*?<a [^>]*?href="(?P<url>[^"]+)"[^>]*>(?P<name>[^<]*)</a>"#).unwrap();
+}
+
+/// ascii2d searcher.
+/// ascii2d does not provide similarity, so hits are returned in the order of
+/// the result page(color search).
+#[derive(Debug, Clone)]
+pub struct Ascii2dSearcher {
+    client: GhostClient,
+}
+
+impl Ascii2dSearcher {
+    pub fn new(prefix: Option<Ipv6Net>) -> Self {
+        Self {
+            client: GhostClient::builder()
+                .with_cf_resolve(&["ascii2d.net"])
+                .build(prefix),
+        }
+    }
+
+    pub fn new_from_config() -> Self {
+        Self {
+            client: GhostClient::builder()
+                .with_cf_resolve(&["ascii2d.net"])
+                .build_from_config()
+                .expect("unable to build client for ascii2d"),
+        }
+    }
+
+    async fn search(client: &reqwest::Client, file: Part) -> anyhow::Result<Vec<SearchHit>> {
+        // get csrf token and session cookie
+        let index = client
+            .get("https://ascii2d.net/")
+            .send()
+            .await
+            .observe()
+            .and_then(Response::error_for_status)?;
+        let cookies = index
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .filter_map(|v| v.split(';').next())
+            .collect::<Vec<_>>()
+            .join("; ");
+        let index = index.text().await?;
+        let token = match_first_group(&CSRF_RE, &index)
+            .ok_or_else(|| anyhow::anyhow!("unable to find ascii2d csrf token"))?
+            .to_string();
+
+        let response = client
+            .post("https://ascii2d.net/search/file")
+            .header(header::COOKIE, cookies)
+            .multipart(
+                multipart::Form::new()
+                    .text("utf8", "✓")
+                    .text("authenticity_token", token)
+                    .part("file", file),
+            )
+            .send()
+            .await
+            .observe()
+            .and_then(Response::error_for_status)?
+            .text()
+            .await?;
+        // check if the response is as expected
+        if !response.contains("item-box") {
+            return Err(anyhow::anyhow!("ascii2d response is not as expected"));
+        }
+        Ok(parse_hits(&response))
+    }
+}
+
+fn parse_hits(html: &str) -> Vec<SearchHit> {
+    DETAIL_RE
+        .captures_iter(html)
+        .map(|cap| {
+            SearchHit::from_url(
+                "ascii2d",
+                cap["url"].to_string(),
+                cap["name"].to_string(),
+                None,
+            )
+        })
+        .collect()
+}
+
+impl ImageSearcher for Ascii2dSearcher {
+    type SeacheError = anyhow::Error;
+    type SearchOutput = Vec<SearchHit>;
+    type FetchFuture<T> = impl Future<Output = Result<Self::SearchOutput, Self::SeacheError>>;
+
+    fn search<T: Into<Cow<'static, [u8]>>>(&self, data: T) -> Self::FetchFuture<T> {
+        let file_part = Part::bytes(data).file_name("image.jpg");
+        let client = self.client.clone();
+        async move { Self::search(&client, file_part).await }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::searcher::{HitId, Site};
+
+    #[test]
+    fn parse() {
+        let html = r#"<div class='row item-box'><div class='hash'>abc</div></div><div class='row item-box'><div class='detail-box gray-link'>
+<h6>
+<img src="/assets/pixiv.ico" width="14" height="14" alt="pixiv">
+<a target="_blank" rel="noopener" href="https://www.pixiv.net/artworks/75943246">some title</a>
+<a target="_blank" rel="noopener" href="https://www.pixiv.net/users/1">author</a>
+<small>pixiv</small>
+</h6>
+</div></div>"#;
+        let hits = parse_hits(html);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].site, Site::Pixiv);
+        assert_eq!(hits[0].id, Some(HitId::Id("75943246".to_string())));
+        assert_eq!(hits[0].name, "some title");
+        assert_eq!(hits[0].similarity, None);
+    }
+}
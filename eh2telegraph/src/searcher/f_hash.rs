@@ -9,8 +9,66 @@ use crate::{
 
 lazy_static::lazy_static! {
     static ref EHENTAI_URL_RE: Regex = Regex::new(r#"<a href="(https://e(-|x)hentai\.org/g/\w+/[\w-]+)/">"#).unwrap();
+    static ref TITLE_RE: Regex = Regex::new(r#"<div class="glink">([^<]*)</div>"#).unwrap();
+    static ref LANGUAGE_RE: Regex = Regex::new(r#"title="language:(\w+)""#).unwrap();
+    static ref PAGES_RE: Regex = Regex::new(r#">(\d+) pages?<"#).unwrap();
 }
-/// FHashConverter can convert f-hash(usually comes from a search result) to the first gallery url.
+
+/// A gallery found by f-hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GalleryCandidate {
+    pub url: String,
+    pub title: String,
+    /// Language tag of the gallery. Japanese galleries usually have no tag.
+    pub language: Option<String>,
+    pub pages: Option<usize>,
+}
+
+impl GalleryCandidate {
+    /// Pick the first candidate with preferred language, or the first one.
+    pub fn pick<'a>(candidates: &'a [Self], language: Option<&str>) -> Option<&'a Self> {
+        language
+            .and_then(|lang| {
+                candidates.iter().find(|c| {
+                    c.language
+                        .as_deref()
+                        .unwrap_or("japanese")
+                        .eq_ignore_ascii_case(lang)
+                })
+            })
+            .or_else(|| candidates.first())
+    }
+}
+
+/// Parse galleries from the search result page.
+fn parse_candidates(html: &str) -> Vec<GalleryCandidate> {
+    html.split("<tr")
+        .filter_map(|row| {
+            let url = match_first_group(&EHENTAI_URL_RE, row)?;
+            Some(GalleryCandidate {
+                url: url.to_string(),
+                title: match_first_group(&TITLE_RE, row)
+                    .map(unescape)
+                    .unwrap_or_default(),
+                language: LANGUAGE_RE
+                    .captures_iter(row)
+                    .map(|c| c[1].to_string())
+                    .find(|l| l != "translated" && l != "rewrite"),
+                pages: match_first_group(&PAGES_RE, row).and_then(|p| p.parse().ok()),
+            })
+        })
+        .collect()
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&#039;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// FHashConverter can convert f-hash(usually comes from a search result) to gallery urls.
 /// Works for both e-hentai and ex-hentai.
 pub struct FHashConvertor {
     client: GhostClient,
@@ -41,28 +99,76 @@ impl FHashConvertor {
         }
     }
 
-    // TODO: impl a trait?
     pub async fn convert_to_gallery(&self, f_hash: &str) -> anyhow::Result<String> {
+        self.find_gallery(f_hash)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("not found in e-hentai or exhentai"))
+    }
+
+    /// Find the first gallery containing the file with given f-hash.
+    /// Returns None if not found.
+    pub async fn find_gallery(&self, f_hash: &str) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .find_galleries(f_hash)
+            .await?
+            .into_iter()
+            .next()
+            .map(|c| c.url))
+    }
+
+    /// Find all galleries containing the file with given f-hash.
+    /// The file may appear in the original, translations and compilations.
+    /// Exhentai is only searched when nothing is found in e-hentai.
+    pub async fn find_galleries(&self, f_hash: &str) -> anyhow::Result<Vec<GalleryCandidate>> {
         tracing::info!("[f-hash] converting hash {f_hash}");
         // find in e-hentai
         let url = format!("https://e-hentai.org/?f_shash={f_hash}&f_sh=on&f_sname=on&f_stags=on&f_sh=on&f_spf=&f_spt=&f_sfl=on&f_sfu=on&f_sft=on");
         let text = get_string(&self.client, &url).await?;
 
-        if let Some(url) = match_first_group(&EHENTAI_URL_RE, &text) {
-            tracing::info!("[f-hash] hash {f_hash} -> {url}");
-            return Ok(url.to_string());
+        let candidates = parse_candidates(&text);
+        if !candidates.is_empty() {
+            tracing::info!("[f-hash] hash {f_hash} -> {} galleries", candidates.len());
+            return Ok(candidates);
         }
 
         // find in exhentai
         let url = format!("https://exhentai.org/?f_shash={f_hash}&f_sh=on&f_sname=on&f_stags=on&f_sh=on&f_spf=&f_spt=&f_sfl=on&f_sfu=on&f_sft=on");
         let text = get_string(&self.raw_client, &url).await?;
 
-        if let Some(url) = match_first_group(&EHENTAI_URL_RE, &text) {
-            tracing::info!("[f-hash] hash {f_hash} -> {url}");
-            return Ok(url.to_string());
+        let candidates = parse_candidates(&text);
+        if !candidates.is_empty() {
+            tracing::info!("[f-hash] hash {f_hash} -> {} galleries", candidates.len());
+            return Ok(candidates);
         }
 
         tracing::info!("[f-hash] hash {f_hash} not found");
-        Err(anyhow::anyhow!("not found in e-hentai or exhentai"))
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let html = r#"<table class="itg gltc"><tr><th>Category</th><th>Title</th></tr><tr><td class="gl1c glcat"><div class="cn ct2">Doujinshi</div></td><td class="gl2c"><div>24 pages</div></td><td class="gl3c glname"><a href="https://e-hentai.org/g/2122174/fd2525031e/"><div class="glink">[Circle] Title</div><div><div class="gt" title="parody:original">original</div></div></a></td></tr><tr><td class="gl1c glcat"><div class="cn ct2">Doujinshi</div></td><td class="gl2c"><div>25 pages</div></td><td class="gl3c glname"><a href="https://e-hentai.org/g/2122180/0123456789/"><div class="glink">[Circle] Title [Chinese] &amp; more</div><div><div class="gt" title="language:translated">translated</div><div class="gt" title="language:chinese">chinese</div></div></a></td></tr></table>"#;
+        let candidates = parse_candidates(html);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(
+            candidates[0].url,
+            "https://e-hentai.org/g/2122174/fd2525031e"
+        );
+        assert_eq!(candidates[0].language, None);
+        assert_eq!(candidates[0].pages, Some(24));
+        assert_eq!(candidates[1].title, "[Circle] Title [Chinese] & more");
+        assert_eq!(candidates[1].language.as_deref(), Some("chinese"));
+
+        let picked = GalleryCandidate::pick(&candidates, Some("Chinese")).unwrap();
+        assert_eq!(picked.pages, Some(25));
+        let picked = GalleryCandidate::pick(&candidates, Some("english")).unwrap();
+        assert_eq!(picked.pages, Some(24));
+        let picked = GalleryCandidate::pick(&candidates, Some("japanese")).unwrap();
+        assert_eq!(picked.pages, Some(24));
     }
 }
@@ -0,0 +1,79 @@
+//! File hash searcher.
+//! E-Hentai indexes images by SHA-1 of the file, so we can compute it locally
+//! and search e-hentai and exhentai directly.
+//! Only unmodified files can be found: photos recompressed by Telegram will
+//! not match, while images sent as files keep their original bytes.
+use std::{borrow::Cow, sync::Arc};
+
+use futures::Future;
+use ipnet::Ipv6Net;
+use sha1::{Digest, Sha1};
+
+use super::{f_hash::FHashConvertor, HitId, ImageSearcher, SearchHit, Site};
+
+/// SHA-1 in lowercase hex, which is the f-hash of e-hentai.
+pub fn file_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha1::digest(data))
+}
+
+pub struct FileHashSearcher {
+    convertor: Arc<FHashConvertor>,
+}
+
+impl FileHashSearcher {
+    pub fn new(prefix: Option<Ipv6Net>) -> Self {
+        Self {
+            convertor: Arc::new(FHashConvertor::new(prefix)),
+        }
+    }
+
+    pub fn new_from_config() -> Self {
+        Self {
+            convertor: Arc::new(FHashConvertor::new_from_config()),
+        }
+    }
+}
+
+impl ImageSearcher for FileHashSearcher {
+    type SeacheError = anyhow::Error;
+    type SearchOutput = Vec<SearchHit>;
+    type FetchFuture<T> = impl Future<Output = Result<Self::SearchOutput, Self::SeacheError>>;
+
+    fn search<T: Into<Cow<'static, [u8]>>>(&self, data: T) -> Self::FetchFuture<T> {
+        let hash = file_hash(&data.into());
+        let convertor = self.convertor.clone();
+        async move {
+            let candidates = convertor.find_galleries(&hash).await?;
+            // the hit is an exact match, and candidates are kept so the
+            // gallery can be picked by language later
+            let hits = candidates
+                .first()
+                .map(|c| SearchHit {
+                    engine: "ehash",
+                    site: Site::EHentai,
+                    url: c.url.clone(),
+                    name: c.title.clone(),
+                    similarity: Some(100),
+                    score: 100,
+                    id: Some(HitId::FileHash(hash)),
+                    candidates: candidates.clone(),
+                })
+                .into_iter()
+                .collect();
+            Ok(hits)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash() {
+        assert_eq!(
+            file_hash(b"abc"),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::borrow::Cow;
+
+use futures::Future;
+use regex::Regex;
+use reqwest::{
+    multipart::{self, Part},
+    Response,
+};
+
+use crate::{http_client::UA, metrics::ObserveExt};
+
+use super::{ImageSearcher, SearchHit};
+
+lazy_static::lazy_static! {
+    static ref MATCH_RE: Regex = Regex::new(r#"(?s)<th>(?:Best|Additional|Possible) match</th></tr><tr><td class=['"]image['"]><a href="(?P<url>[^"]+)"><img [^>]*?alt="(?P<alt>[^"]*)".*?(?P<sim>\d+)% similarity"#).unwrap();
+}
+
+/// iqdb searcher.
+/// iqdb indexes boorus like danbooru, gelbooru, yande.re and konachan.
+#[derive(Debug, Clone)]
+pub struct IqdbSearcher {
+    client: reqwest::Client,
+}
+
+impl Default for IqdbSearcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IqdbSearcher {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .user_agent(UA)
+                .build()
+                .expect("unable to build client for iqdb"),
+        }
+    }
+
+    async fn search(client: &reqwest::Client, file: Part) -> anyhow::Result<Vec<SearchHit>> {
+        let response = client
+            .post("https://iqdb.org/")
+            .multipart(multipart::Form::new().part("file", file))
+            .send()
+            .await
+            .observe()
+            .and_then(Response::error_for_status)?
+            .text()
+            .await?;
+        // check if the response is as expected
+        if !response.contains("Your image") {
+            return Err(anyhow::anyhow!("iqdb response is not as expected"));
+        }
+        Ok(parse_hits(&response))
+    }
+}
+
+fn parse_hits(html: &str) -> Vec<SearchHit> {
+    let mut hits: Vec<_> = MATCH_RE
+        .captures_iter(html)
+        .map(|cap| {
+            let url = &cap["url"];
+            let url = if url.starts_with("//") {
+                format!("https:{url}")
+            } else {
+                url.to_string()
+            };
+            let similarity = cap["sim"].parse().ok();
+            SearchHit::from_url("iqdb", url, cap["alt"].to_string(), similarity)
+        })
+        .collect();
+    hits.sort_by_key(|h| std::cmp::Reverse(h.similarity));
+    hits
+}
+
+impl ImageSearcher for IqdbSearcher {
+    type SeacheError = anyhow::Error;
+    type SearchOutput = Vec<SearchHit>;
+    type FetchFuture<T> = impl Future<Output = Result<Self::SearchOutput, Self::SeacheError>>;
+
+    fn search<T: Into<Cow<'static, [u8]>>>(&self, data: T) -> Self::FetchFuture<T> {
+        let file_part = Part::bytes(data).file_name("image.jpg");
+        let client = self.client.clone();
+        async move { Self::search(&client, file_part).await }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::searcher::{HitId, Site};
+
+    #[test]
+    fn parse() {
+        let html = r#"<div id="pages" class="pages"><div><table><tr><th>Your image</th></tr></table></div><div><table><tr><th>Best match</th></tr><tr><td class='image'><a href="//danbooru.donmai.us/posts/5012345"><img src='/danbooru/a.jpg' alt="Rating: s Score: 10 Tags: 1girl solo" title="Rating: s" width='150' height='112'></a></td></tr><tr><td>Danbooru</td></tr><tr><td>1200×900 [Safe]</td></tr><tr><td>94% similarity</td></tr></table></div><div><table><tr><th>Additional match</th></tr><tr><td class='image'><a href="https://yande.re/post/show/123"><img src='/moe.imouto/b.jpg' alt="Rating: s" width='150' height='112'></a></td></tr><tr><td>yande.re</td></tr><tr><td>92% similarity</td></tr></table></div></div>"#;
+        let hits = parse_hits(html);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].url, "https://danbooru.donmai.us/posts/5012345");
+        assert_eq!(hits[0].site, Site::Danbooru);
+        assert_eq!(hits[0].id, Some(HitId::Id("5012345".to_string())));
+        assert_eq!(hits[0].similarity, Some(94));
+        assert_eq!(hits[1].site, Site::Yandere);
+        assert_eq!(hits[1].similarity, Some(92));
+    }
+}
@@ -0,0 +1,234 @@
+//! Local searcher.
+//! Perceptual hashes(dHash) of synced images are saved in an index, so photos
+//! of synced galleries can be found without asking remote engines.
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+use futures::Future;
+use image::imageops::FilterType;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::KVStorage;
+
+use super::{ImageSearcher, SearchHit};
+
+const KEY_PREFIX: &str = "phash|";
+const DEFAULT_MAX_DISTANCE: u32 = 10;
+
+/// Compute 64-bit difference hash of the image.
+/// Returns None if the image cannot be decoded.
+pub fn dhash(data: &[u8]) -> Option<u64> {
+    let image = image::load_from_memory(data).ok()?;
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = small.get_pixel(x, y)[0];
+            let right = small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | (left > right) as u64;
+        }
+    }
+    Some(hash)
+}
+
+#[inline]
+pub fn hamming(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// A synced gallery with hashes of its images.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedGallery {
+    /// Original gallery link.
+    pub link: String,
+    pub title: String,
+    pub telegraph: String,
+    pub hashes: Vec<u64>,
+}
+
+/// Index from image hash to gallery.
+/// All entries are kept in memory, and persisted to storage with key
+/// `phash|{link}`.
+#[derive(Debug, Clone)]
+pub struct PHashIndex<S> {
+    galleries: Arc<RwLock<HashMap<String, Arc<IndexedGallery>>>>,
+    storage: S,
+}
+
+impl<S> PHashIndex<S>
+where
+    S: KVStorage<String>,
+{
+    pub fn new(storage: S) -> Self {
+        Self {
+            galleries: Default::default(),
+            storage,
+        }
+    }
+
+    /// Load all persisted entries into memory.
+    /// Returns the count of loaded galleries.
+    pub async fn load(&self) -> anyhow::Result<usize> {
+        let keys = self.storage.scan(KEY_PREFIX).await?;
+        let mut count = 0;
+        for info in keys {
+            let value = match self.storage.get(&info.key).await? {
+                Some(v) => v,
+                None => continue,
+            };
+            match serde_json::from_str::<IndexedGallery>(&value) {
+                Ok(g) => {
+                    self.galleries.write().insert(g.link.clone(), Arc::new(g));
+                    count += 1;
+                }
+                Err(e) => tracing::warn!("[phash] invalid entry {}: {e}", info.key),
+            }
+        }
+        tracing::info!("[phash] loaded {count} galleries");
+        Ok(count)
+    }
+
+    /// Add or replace a gallery.
+    pub async fn insert(&self, gallery: IndexedGallery) -> anyhow::Result<()> {
+        let value = serde_json::to_string(&gallery)?;
+        self.storage
+            .set(format!("{KEY_PREFIX}{}", gallery.link), value, None)
+            .await?;
+        self.galleries
+            .write()
+            .insert(gallery.link.clone(), Arc::new(gallery));
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.galleries.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.galleries.read().is_empty()
+    }
+
+    /// Find galleries containing an image within max_distance to the hash.
+    /// Results are sorted by distance.
+    pub fn find(&self, hash: u64, max_distance: u32) -> Vec<(u32, Arc<IndexedGallery>)> {
+        let mut found: Vec<_> = self
+            .galleries
+            .read()
+            .values()
+            .filter_map(|g| {
+                g.hashes
+                    .iter()
+                    .map(|h| hamming(*h, hash))
+                    .min()
+                    .filter(|d| *d <= max_distance)
+                    .map(|d| (d, g.clone()))
+            })
+            .collect();
+        found.sort_by_key(|(d, _)| *d);
+        found
+    }
+}
+
+/// Searcher which answers from the local hash index.
+#[derive(Debug, Clone)]
+pub struct LocalSearcher<S> {
+    index: PHashIndex<S>,
+    max_distance: u32,
+}
+
+impl<S> LocalSearcher<S> {
+    pub fn new(index: PHashIndex<S>) -> Self {
+        Self {
+            index,
+            max_distance: DEFAULT_MAX_DISTANCE,
+        }
+    }
+
+    pub fn with_max_distance(mut self, max_distance: u32) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+}
+
+impl<S> ImageSearcher for LocalSearcher<S>
+where
+    S: KVStorage<String> + Clone + Send + Sync + 'static,
+{
+    type SeacheError = anyhow::Error;
+    type SearchOutput = Vec<SearchHit>;
+    type FetchFuture<T> = impl Future<Output = Result<Self::SearchOutput, Self::SeacheError>>;
+
+    fn search<T: Into<Cow<'static, [u8]>>>(&self, data: T) -> Self::FetchFuture<T> {
+        let data = data.into().into_owned();
+        let index = self.index.clone();
+        let max_distance = self.max_distance;
+        async move {
+            if index.is_empty() {
+                return Ok(Vec::new());
+            }
+            let hash = tokio::task::spawn_blocking(move || dhash(&data))
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("unable to decode image"))?;
+            Ok(index
+                .find(hash, max_distance)
+                .into_iter()
+                .map(|(distance, g)| {
+                    let similarity = 100 - (distance * 100 / 64) as u8;
+                    SearchHit::from_url("local", g.link.clone(), g.title.clone(), Some(similarity))
+                })
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{ImageOutputFormat, RgbImage};
+
+    use super::*;
+    use crate::storage::SimpleMemStorage;
+
+    fn encode(f: impl Fn(u32, u32) -> [u8; 3]) -> Vec<u8> {
+        let image = RgbImage::from_fn(64, 48, |x, y| image::Rgb(f(x, y)));
+        let mut buf = Cursor::new(Vec::new());
+        image.write_to(&mut buf, ImageOutputFormat::Png).unwrap();
+        buf.into_inner()
+    }
+
+    #[test]
+    fn hash_distance() {
+        let gradient = encode(|x, y| [(x * 3) as u8, (y * 5) as u8, 128]);
+        let brighter = encode(|x, y| [(x * 3 + 10) as u8, (y * 5 + 10) as u8, 138]);
+        let reversed = encode(|x, y| [255 - (x * 3) as u8, (y * 5) as u8, 128]);
+        let a = dhash(&gradient).unwrap();
+        assert!(hamming(a, dhash(&brighter).unwrap()) <= 4);
+        assert!(hamming(a, dhash(&reversed).unwrap()) > 32);
+        assert_eq!(dhash(b"not an image"), None);
+    }
+
+    #[tokio::test]
+    async fn index() {
+        let storage = SimpleMemStorage::default();
+        let index = PHashIndex::new(storage.clone());
+        index
+            .insert(IndexedGallery {
+                link: "https://e-hentai.org/g/1/a/".to_string(),
+                title: "t".to_string(),
+                telegraph: "https://telegra.ph/t".to_string(),
+                hashes: vec![0, u64::MAX],
+            })
+            .await
+            .unwrap();
+        assert_eq!(index.find(0b111, 4).len(), 1);
+        assert!(index.find(0xffff_0000, 4).is_empty());
+
+        let loaded = PHashIndex::new(storage);
+        assert_eq!(loaded.load().await.unwrap(), 1);
+        assert_eq!(
+            loaded.find(u64::MAX, 0)[0].1.telegraph,
+            "https://telegra.ph/t"
+        );
+    }
+}
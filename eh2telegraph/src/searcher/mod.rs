@@ -1,4 +1,16 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use self::f_hash::GalleryCandidate;
+
+pub mod aggregate;
+pub mod ascii2d;
 pub mod f_hash;
+pub mod file_hash;
+pub mod iqdb;
+pub mod local;
+pub mod resolver;
 pub mod saucenao;
 
 pub trait ImageSearcher {
@@ -9,6 +21,106 @@ pub trait ImageSearcher {
     fn search<T: Into<std::borrow::Cow<'static, [u8]>>>(&self, data: T) -> Self::FetchFuture<T>;
 }
 
+/// Site of a search hit.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Site {
+    EHentai,
+    ExHentai,
+    NHentai,
+    Pixiv,
+    Twitter,
+    Danbooru,
+    Gelbooru,
+    Yandere,
+    Konachan,
+    Sankaku,
+    Other,
+}
+
+/// Parsed identity of a search hit.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HitId {
+    /// SHA-1 of the image file(f-hash on e-hentai).
+    FileHash(String),
+    /// Gallery or post id of the site.
+    Id(String),
+}
+
+/// Common search result of image searchers.
+/// Only the fields for users are serialized.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    /// Name of the engine which produced the hit.
+    pub engine: &'static str,
+    pub site: Site,
+    pub url: String,
+    pub name: String,
+    /// Similarity in percent. Some engines(like ascii2d) do not provide it.
+    pub similarity: Option<u8>,
+    /// Normalized score in percent, which is comparable between engines.
+    pub score: u8,
+    #[serde(skip)]
+    pub id: Option<HitId>,
+    /// Galleries already found for the hit, so resolvers need not look them up again.
+    #[serde(skip)]
+    pub candidates: Vec<GalleryCandidate>,
+}
+
+impl SearchHit {
+    /// Create a hit with site and id parsed from url.
+    pub fn from_url(
+        engine: &'static str,
+        url: String,
+        name: String,
+        similarity: Option<u8>,
+    ) -> Self {
+        let (site, id) = parse_site_url(&url);
+        Self {
+            engine,
+            site,
+            url,
+            name,
+            similarity,
+            score: similarity.unwrap_or_default(),
+            id: id.map(HitId::Id),
+            candidates: Vec::new(),
+        }
+    }
+}
+
+static SITE_URL_RE: Lazy<Vec<(Site, Regex)>> = Lazy::new(|| {
+    [
+        (Site::EHentai, r#"e-hentai\.org/g/(\d+/[\w-]+)"#),
+        (Site::ExHentai, r#"exhentai\.org/g/(\d+/[\w-]+)"#),
+        (Site::NHentai, r#"nhentai\.(?:net|to)/g/(\d+)"#),
+        (
+            Site::Pixiv,
+            r#"pixiv\.net/(?:(?:\w+/)?artworks/|member_illust\.php\?.*illust_id=)(\d+)"#,
+        ),
+        (Site::Twitter, r#"twitter\.com/\w+/status/(\d+)"#),
+        (Site::Danbooru, r#"danbooru\.donmai\.us/posts/(\d+)"#),
+        (Site::Gelbooru, r#"gelbooru\.com/index\.php\?.*id=(\d+)"#),
+        (Site::Yandere, r#"yande\.re/post/show/(\d+)"#),
+        (Site::Konachan, r#"konachan\.(?:com|net)/post/show/(\d+)"#),
+        (Site::Sankaku, r#"sankakucomplex\.com/post/show/(\w+)"#),
+    ]
+    .into_iter()
+    .map(|(site, re)| (site, Regex::new(re).unwrap()))
+    .collect()
+});
+
+/// Parse site and gallery or post id from url.
+pub fn parse_site_url(url: &str) -> (Site, Option<String>) {
+    for (site, re) in SITE_URL_RE.iter() {
+        if let Some(id) = crate::util::match_first_group(re, url) {
+            return (*site, Some(id.to_string()));
+        }
+    }
+    (Site::Other, None)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -21,4 +133,66 @@ mod tests {
         let r = searcher.search(data).await;
         println!("result: {r:?}");
     }
+
+    #[test]
+    fn serialize_hit() {
+        let hit = SearchHit::from_url(
+            "iqdb",
+            "https://e-hentai.org/g/2122174/fd2525031e/".to_string(),
+            "name".to_string(),
+            Some(90),
+        );
+        let value = serde_json::to_value(&hit).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "engine": "iqdb",
+                "site": "EHentai",
+                "url": "https://e-hentai.org/g/2122174/fd2525031e/",
+                "name": "name",
+                "similarity": 90,
+                "score": 90,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_url() {
+        let cases = [
+            (
+                "https://e-hentai.org/g/2122174/fd2525031e/",
+                Site::EHentai,
+                Some("2122174/fd2525031e"),
+            ),
+            (
+                "https://nhentai.net/g/333678",
+                Site::NHentai,
+                Some("333678"),
+            ),
+            (
+                "https://www.pixiv.net/artworks/75943246",
+                Site::Pixiv,
+                Some("75943246"),
+            ),
+            (
+                "https://www.pixiv.net/member_illust.php?mode=medium&illust_id=75943246",
+                Site::Pixiv,
+                Some("75943246"),
+            ),
+            (
+                "https://danbooru.donmai.us/posts/5012345",
+                Site::Danbooru,
+                Some("5012345"),
+            ),
+            (
+                "https://gelbooru.com/index.php?page=post&s=view&id=123",
+                Site::Gelbooru,
+                Some("123"),
+            ),
+            ("https://example.com/1", Site::Other, None),
+        ];
+        for (url, site, id) in cases {
+            assert_eq!(parse_site_url(url), (site, id.map(ToString::to_string)));
+        }
+    }
 }
@@ -0,0 +1,239 @@
+//! Gallery resolvers.
+//! A resolver turns a search hit into a syncable gallery url and the collector
+//! which can sync it.
+//! Pixiv hits are not resolved since there is no pixiv collector yet.
+use std::sync::Arc;
+
+use futures::{future::BoxFuture, Future};
+
+use crate::{
+    collector::{e_hentai::EHCollector, exhentai::EXCollector, nhentai::NHCollector, Collector},
+    sync::Synchronizer,
+};
+
+use super::{
+    f_hash::{FHashConvertor, GalleryCandidate},
+    HitId, SearchHit, Site,
+};
+
+/// Syncable gallery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolved {
+    pub url: String,
+    /// Name of the collector.
+    pub collector: &'static str,
+}
+
+impl Resolved {
+    /// Create with collector found by url.
+    /// Returns None if no collector matches.
+    pub fn from_url(url: &str) -> Option<Self> {
+        let url = Synchronizer::match_url_from_text(url)?;
+        let collector = if url.starts_with("https://e-hentai.org/") {
+            EHCollector::name()
+        } else if url.starts_with("https://exhentai.org/") {
+            EXCollector::name()
+        } else {
+            NHCollector::name()
+        };
+        Some(Self {
+            url: url.to_string(),
+            collector,
+        })
+    }
+}
+
+pub trait GalleryResolver {
+    type ResolveFuture<'a>: Future<Output = anyhow::Result<Option<Resolved>>> + Send
+    where
+        Self: 'a;
+
+    /// Resolve the hit. Returns None if the hit is not supported by this resolver.
+    /// Language is used when there are multiple galleries.
+    fn resolve<'a>(
+        &'a self,
+        hit: &'a SearchHit,
+        language: Option<&'a str>,
+    ) -> Self::ResolveFuture<'a>;
+}
+
+/// Resolve e-hentai f-hash hits.
+pub struct FHashResolver {
+    convertor: Arc<FHashConvertor>,
+}
+
+impl FHashResolver {
+    pub fn new(convertor: Arc<FHashConvertor>) -> Self {
+        Self { convertor }
+    }
+}
+
+impl GalleryResolver for FHashResolver {
+    type ResolveFuture<'a> = impl Future<Output = anyhow::Result<Option<Resolved>>> + Send;
+
+    fn resolve<'a>(
+        &'a self,
+        hit: &'a SearchHit,
+        language: Option<&'a str>,
+    ) -> Self::ResolveFuture<'a> {
+        async move {
+            let f_hash = match (hit.site, &hit.id) {
+                (Site::EHentai | Site::ExHentai, Some(HitId::FileHash(f))) => f,
+                _ => return Ok(None),
+            };
+            let found;
+            let candidates = if hit.candidates.is_empty() {
+                found = self.convertor.find_galleries(f_hash).await?;
+                &found
+            } else {
+                &hit.candidates
+            };
+            Ok(GalleryCandidate::pick(candidates, language)
+                .and_then(|c| Resolved::from_url(&c.url)))
+        }
+    }
+}
+
+/// Resolve nhentai id hits.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NHentaiResolver;
+
+impl GalleryResolver for NHentaiResolver {
+    type ResolveFuture<'a> = impl Future<Output = anyhow::Result<Option<Resolved>>> + Send;
+
+    fn resolve<'a>(
+        &'a self,
+        hit: &'a SearchHit,
+        _language: Option<&'a str>,
+    ) -> Self::ResolveFuture<'a> {
+        let resolved = match (hit.site, &hit.id) {
+            (Site::NHentai, Some(HitId::Id(nid))) => Some(Resolved {
+                url: format!("https://nhentai.net/g/{nid}/"),
+                collector: NHCollector::name(),
+            }),
+            _ => None,
+        };
+        async move { Ok(resolved) }
+    }
+}
+
+/// Resolve hits whose url is already a syncable gallery url.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UrlResolver;
+
+impl GalleryResolver for UrlResolver {
+    type ResolveFuture<'a> = impl Future<Output = anyhow::Result<Option<Resolved>>> + Send;
+
+    fn resolve<'a>(
+        &'a self,
+        hit: &'a SearchHit,
+        _language: Option<&'a str>,
+    ) -> Self::ResolveFuture<'a> {
+        let resolved = Resolved::from_url(&hit.url);
+        async move { Ok(resolved) }
+    }
+}
+
+trait DynResolver: Send + Sync {
+    fn resolve_boxed<'a>(
+        &'a self,
+        hit: &'a SearchHit,
+        language: Option<&'a str>,
+    ) -> BoxFuture<'a, anyhow::Result<Option<Resolved>>>;
+}
+
+impl<R> DynResolver for R
+where
+    R: GalleryResolver + Send + Sync,
+{
+    fn resolve_boxed<'a>(
+        &'a self,
+        hit: &'a SearchHit,
+        language: Option<&'a str>,
+    ) -> BoxFuture<'a, anyhow::Result<Option<Resolved>>> {
+        Box::pin(self.resolve(hit, language))
+    }
+}
+
+/// Try resolvers in order, and return the first resolved gallery.
+#[derive(Default)]
+pub struct ResolverChain {
+    resolvers: Vec<Box<dyn DynResolver>>,
+}
+
+impl ResolverChain {
+    /// Chain with all built-in resolvers.
+    pub fn new(convertor: Arc<FHashConvertor>) -> Self {
+        Self::default()
+            .with_resolver(FHashResolver::new(convertor))
+            .with_resolver(NHentaiResolver)
+            .with_resolver(UrlResolver)
+    }
+
+    pub fn with_resolver<R>(mut self, resolver: R) -> Self
+    where
+        R: GalleryResolver + Send + Sync + 'static,
+    {
+        self.resolvers.push(Box::new(resolver));
+        self
+    }
+}
+
+impl GalleryResolver for ResolverChain {
+    type ResolveFuture<'a> = impl Future<Output = anyhow::Result<Option<Resolved>>> + Send;
+
+    fn resolve<'a>(
+        &'a self,
+        hit: &'a SearchHit,
+        language: Option<&'a str>,
+    ) -> Self::ResolveFuture<'a> {
+        async move {
+            for resolver in self.resolvers.iter() {
+                if let Some(r) = resolver.resolve_boxed(hit, language).await? {
+                    return Ok(Some(r));
+                }
+            }
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn resolve() {
+        let chain = ResolverChain::default()
+            .with_resolver(NHentaiResolver)
+            .with_resolver(UrlResolver);
+
+        let hit = SearchHit::from_url(
+            "test",
+            "https://nhentai.to/g/333678".to_string(),
+            String::new(),
+            None,
+        );
+        let r = chain.resolve(&hit, None).await.unwrap().unwrap();
+        assert_eq!(r.url, "https://nhentai.net/g/333678/");
+        assert_eq!(r.collector, "nhentai");
+
+        let hit = SearchHit::from_url(
+            "test",
+            "https://exhentai.org/g/2122174/fd2525031e/".to_string(),
+            String::new(),
+            None,
+        );
+        let r = chain.resolve(&hit, None).await.unwrap().unwrap();
+        assert_eq!(r.url, "https://exhentai.org/g/2122174/fd2525031e");
+        assert_eq!(r.collector, "exhentai");
+
+        let hit = SearchHit::from_url(
+            "test",
+            "https://www.pixiv.net/artworks/75943246".to_string(),
+            String::new(),
+            None,
+        );
+        assert_eq!(chain.resolve(&hit, None).await.unwrap(), None);
+    }
+}
@@ -1,16 +1,29 @@
-use std::{borrow::Cow, str::FromStr};
+use std::{
+    borrow::Cow,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use futures::Future;
 use ipnet::Ipv6Net;
+use parking_lot::Mutex;
 use regex::Regex;
 use reqwest::{
     multipart::{self, Part},
-    Response,
+    Response, StatusCode,
 };
+use serde::Deserialize;
+
+use crate::{config, http_client::GhostClient, metrics::ObserveExt};
 
-use crate::http_client::GhostClient;
+use super::{parse_site_url, HitId, ImageSearcher, SearchHit, Site};
 
-use super::ImageSearcher;
+const CONFIG_KEY: &str = "saucenao";
+// The short limit of saucenao is counted in 30 seconds, and the long limit is
+// counted in 24 hours.
+const SHORT_BACKOFF: Duration = Duration::from_secs(30);
+const LONG_BACKOFF: Duration = Duration::from_secs(24 * 3600);
 
 lazy_static::lazy_static! {
     static ref SEARCH_ELEMENT_RE: Regex = Regex::new(r#"<tr><td class="resulttableimage">(.*?)</tr>"#).unwrap();
@@ -42,11 +55,71 @@ macro_rules! extract_first_opt {
     };
 }
 
+#[derive(Debug, Deserialize, Default)]
+pub struct SaucenaoConfig {
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+}
+
+#[derive(Debug)]
+struct ApiKey {
+    key: String,
+    blocked_until: Mutex<Option<Instant>>,
+}
+
+impl ApiKey {
+    fn is_available(&self, now: Instant) -> bool {
+        match *self.blocked_until.lock() {
+            Some(t) => t <= now,
+            None => true,
+        }
+    }
+
+    fn block(&self, duration: Duration) {
+        tracing::warn!(
+            "[saucenao] api key {}*** quota exhausted, back off for {}s",
+            &self.key[..self.key.len().min(4)],
+            duration.as_secs()
+        );
+        *self.blocked_until.lock() = Some(Instant::now() + duration);
+    }
+}
+
+/// Saucenao api keys.
+/// Like `RandomAccessToken`, an available key is selected randomly.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys(Arc<Vec<ApiKey>>);
+
+impl ApiKeys {
+    fn select(&self) -> Option<&ApiKey> {
+        use rand::prelude::SliceRandom;
+        let now = Instant::now();
+        let available: Vec<_> = self.0.iter().filter(|k| k.is_available(now)).collect();
+        available.choose(&mut rand::thread_rng()).copied()
+    }
+}
+
+impl From<Vec<String>> for ApiKeys {
+    fn from(keys: Vec<String>) -> Self {
+        Self(Arc::new(
+            keys.into_iter()
+                .map(|key| ApiKey {
+                    key,
+                    blocked_until: Mutex::new(None),
+                })
+                .collect(),
+        ))
+    }
+}
+
 /// Saucenao searcher.
+/// If api keys are configured, the json api is used, and the html page will be
+/// used as a fallback.
 /// Note: even saucenao resolves to an ipv6 address, we still use force resolving.
 #[derive(Debug, Clone)]
 pub struct SaucenaoSearcher {
     client: GhostClient,
+    api_keys: ApiKeys,
 }
 
 impl SaucenaoSearcher {
@@ -55,24 +128,99 @@ impl SaucenaoSearcher {
             client: GhostClient::builder()
                 .with_cf_resolve(&["saucenao.com", "e-hentai.org"])
                 .build(prefix),
+            api_keys: ApiKeys::default(),
         }
     }
 
     pub fn new_from_config() -> Self {
+        let config: SaucenaoConfig = config::parse(CONFIG_KEY)
+            .expect("unable to parse saucenao config")
+            .unwrap_or_default();
         Self {
             client: GhostClient::builder()
                 .with_cf_resolve(&["saucenao.com", "e-hentai.org"])
                 .build_from_config()
                 .expect("unable to build client for saucenao"),
+            api_keys: config.api_keys.into(),
         }
     }
 
-    async fn search(client: &reqwest::Client, file: Part) -> anyhow::Result<SaucenaoOutput> {
+    pub fn with_api_keys<K: Into<ApiKeys>>(mut self, api_keys: K) -> Self {
+        self.api_keys = api_keys.into();
+        self
+    }
+
+    async fn search(
+        client: &reqwest::Client,
+        api_keys: &ApiKeys,
+        data: Vec<u8>,
+    ) -> anyhow::Result<SaucenaoOutput> {
+        if let Some(key) = api_keys.select() {
+            let file = Part::bytes(data.clone()).file_name("image.jpg");
+            match Self::search_api(client, key, file).await {
+                Ok(output) => return Ok(output),
+                Err(e) => {
+                    tracing::warn!("[saucenao] api search failed, fallback to html: {e}");
+                }
+            }
+        }
+        let file = Part::bytes(data).file_name("image.jpg");
+        Self::search_html(client, file).await
+    }
+
+    async fn search_api(
+        client: &reqwest::Client,
+        key: &ApiKey,
+        file: Part,
+    ) -> anyhow::Result<SaucenaoOutput> {
         let response = client
             .post("https://saucenao.com/search.php")
+            .query(&[
+                ("output_type", "2"),
+                ("numres", "16"),
+                ("api_key", key.key.as_str()),
+            ])
             .multipart(multipart::Form::new().part("file", file))
             .send()
             .await
+            .observe()?;
+        match response.status() {
+            StatusCode::TOO_MANY_REQUESTS => {
+                key.block(SHORT_BACKOFF);
+                return Err(anyhow::anyhow!("saucenao api rate limited"));
+            }
+            StatusCode::FORBIDDEN => {
+                key.block(LONG_BACKOFF);
+                return Err(anyhow::anyhow!("saucenao api key rejected"));
+            }
+            _ => (),
+        }
+        let response: ApiResponse = response.error_for_status()?.json().await?;
+
+        // track quota
+        let header = &response.header;
+        if header.long_remaining.map(|r| r <= 0).unwrap_or_default() {
+            key.block(LONG_BACKOFF);
+        } else if header.short_remaining.map(|r| r <= 0).unwrap_or_default() {
+            key.block(SHORT_BACKOFF);
+        }
+        if header.status != 0 {
+            return Err(anyhow::anyhow!(
+                "saucenao api returns status {}: {}",
+                header.status,
+                header.message.as_deref().unwrap_or_default()
+            ));
+        }
+        Ok(response.into())
+    }
+
+    async fn search_html(client: &reqwest::Client, file: Part) -> anyhow::Result<SaucenaoOutput> {
+        let response = client
+            .post("https://saucenao.com/search.php")
+            .multipart(multipart::Form::new().part("file", file))
+            .send()
+            .await
+            .observe()
             .and_then(Response::error_for_status)?
             .text()
             .await?;
@@ -84,6 +232,41 @@ impl SaucenaoSearcher {
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    header: ApiHeader,
+    #[serde(default)]
+    results: Vec<ApiResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiHeader {
+    status: i64,
+    short_remaining: Option<i64>,
+    long_remaining: Option<i64>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResult {
+    header: ApiResultHeader,
+    data: ApiResultData,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResultHeader {
+    similarity: String,
+    thumbnail: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResultData {
+    title: Option<String>,
+    eng_name: Option<String>,
+    jp_name: Option<String>,
+    source: Option<String>,
+}
+
 #[non_exhaustive]
 #[derive(Debug, Clone)]
 pub enum SaucenaoParsed {
@@ -121,9 +304,88 @@ impl ImageSearcher for SaucenaoSearcher {
     type FetchFuture<T> = impl Future<Output = Result<Self::SearchOutput, Self::SeacheError>>;
 
     fn search<T: Into<Cow<'static, [u8]>>>(&self, data: T) -> Self::FetchFuture<T> {
-        let file_part = Part::bytes(data).file_name("image.jpg");
+        let data = data.into().into_owned();
         let client = self.client.clone();
-        async move { Self::search(&client, file_part).await }
+        let api_keys = self.api_keys.clone();
+        async move { Self::search(&client, &api_keys, data).await }
+    }
+}
+
+impl From<ApiResponse> for SaucenaoOutput {
+    fn from(r: ApiResponse) -> Self {
+        let mut data: Vec<_> = r
+            .results
+            .into_iter()
+            .map(|item| {
+                let similarity = item
+                    .header
+                    .similarity
+                    .parse::<f32>()
+                    .map(|s| s as u8)
+                    .unwrap_or_default();
+                let name = [
+                    item.data.title,
+                    item.data.eng_name,
+                    item.data.jp_name,
+                    item.data.source,
+                ]
+                .into_iter()
+                .flatten()
+                .find(|n| !n.is_empty())
+                .unwrap_or_else(|| "NO TITLE".to_string());
+                let parsed = parse_site(&item.header.thumbnail);
+                SaucenaoOuputElement {
+                    raw_url: item.header.thumbnail,
+                    name,
+                    similarity,
+                    parsed,
+                }
+            })
+            .collect();
+        data.sort_unstable_by(|a, b| b.similarity.cmp(&a.similarity));
+        Self { data }
+    }
+}
+
+fn parse_site(raw_url: &str) -> SaucenaoParsed {
+    SITE_PARSE_RE
+        .captures(raw_url)
+        .and_then(|cap| {
+            if let Some(pixiv) = cap.name("pixiv_id") {
+                return Some(SaucenaoParsed::Pixiv(pixiv.as_str().to_string()));
+            }
+            if let Some(eh) = cap.name("ehentai_fhash") {
+                return Some(SaucenaoParsed::EHentai(eh.as_str().to_string()));
+            }
+            if let Some(nh) = cap.name("nhentai_id") {
+                return Some(SaucenaoParsed::NHentai(nh.as_str().to_string()));
+            }
+            None
+        })
+        .unwrap_or(SaucenaoParsed::Other)
+}
+
+impl From<SaucenaoOuputElement> for SearchHit {
+    fn from(e: SaucenaoOuputElement) -> Self {
+        let (site, id) = match e.parsed {
+            SaucenaoParsed::EHentai(f_hash) => (Site::EHentai, Some(HitId::FileHash(f_hash))),
+            SaucenaoParsed::NHentai(id) => (Site::NHentai, Some(HitId::Id(id))),
+            SaucenaoParsed::Pixiv(id) => (Site::Pixiv, Some(HitId::Id(id))),
+            SaucenaoParsed::Other => {
+                let (site, id) = parse_site_url(&e.raw_url);
+                (site, id.map(HitId::Id))
+            }
+        };
+        Self {
+            engine: "saucenao",
+            site,
+            url: e.raw_url,
+            name: e.name,
+            similarity: Some(e.similarity),
+            score: e.similarity,
+            id,
+            candidates: Vec::new(),
+        }
     }
 }
 
@@ -163,21 +425,7 @@ impl FromStr for SaucenaoOuputElement {
         let similarity =
             extract_first!(SIM_RE, s, "unable to parse saucenao result similarity").parse()?;
 
-        let parsed = SITE_PARSE_RE
-            .captures(&raw_url)
-            .and_then(|cap| {
-                if let Some(pixiv) = cap.name("pixiv_id") {
-                    return Some(SaucenaoParsed::Pixiv(pixiv.as_str().to_string()));
-                }
-                if let Some(eh) = cap.name("ehentai_fhash") {
-                    return Some(SaucenaoParsed::EHentai(eh.as_str().to_string()));
-                }
-                if let Some(nh) = cap.name("nhentai_id") {
-                    return Some(SaucenaoParsed::NHentai(nh.as_str().to_string()));
-                }
-                None
-            })
-            .unwrap_or(SaucenaoParsed::Other);
+        let parsed = parse_site(&raw_url);
 
         Ok(Self {
             raw_url,
@@ -187,3 +435,27 @@ impl FromStr for SaucenaoOuputElement {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_api_response() {
+        let r = r#"{"header":{"user_id":"1","account_type":"1","short_limit":"4","long_limit":"100","long_remaining":99,"short_remaining":3,"status":0,"results_requested":16},"results":[{"header":{"similarity":"63.12","thumbnail":"https://img1.saucenao.com/res/nhentai/177013.jpg?auth=x","index_id":18,"index_name":"Index #18"},"data":{"source":"","eng_name":"nh name","jp_name":""}},{"header":{"similarity":"94.31","thumbnail":"https://img3.saucenao.com/ehentai/c5/17/c517710f0654ea883df1e0fea7117c671fb03bc1.jpg?auth=x","index_id":38,"index_name":"Index #38"},"data":{"source":"eh source","creator":["a"]}}]}"#;
+        let response: ApiResponse = serde_json::from_str(r).unwrap();
+        assert_eq!(response.header.short_remaining, Some(3));
+        assert_eq!(response.header.long_remaining, Some(99));
+
+        let output = SaucenaoOutput::from(response);
+        assert_eq!(output.data.len(), 2);
+        assert_eq!(output.data[0].similarity, 94);
+        assert_eq!(output.data[0].name, "eh source");
+        assert!(matches!(
+            &output.data[0].parsed,
+            SaucenaoParsed::EHentai(h) if h == "c517710f0654ea883df1e0fea7117c671fb03bc1"
+        ));
+        assert_eq!(output.data[1].name, "nh name");
+        assert!(matches!(&output.data[1].parsed, SaucenaoParsed::NHentai(id) if id == "177013"));
+    }
+}
@@ -0,0 +1,292 @@
+//! Usage statistics.
+//! Counters are accumulated in memory and flushed to storage periodically.
+//! Each day(UTC) has its own bucket, and all-time counters are saved separately.
+//!
+//! Counter names:
+//! - `sync|{collector}`: galleries synced, cache hits excluded
+//! - `cache|hit`, `cache|miss`
+//! - `upload|images`, `upload|bytes`
+//! - `search|{engine}`: searches with hits from the engine
+//! - `failure|{kind}`: kinds are `fetch`, `download`, `telegraph` and `search`
+//! - `chat|{id}`: sync requests from the chat
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::Mutex;
+
+use crate::storage::KVStorage;
+
+const KEY_PREFIX: &str = "stats|";
+const DAY_SECS: u64 = 24 * 60 * 60;
+// daily buckets are only used by daily and weekly views
+const DAY_TTL: usize = 30 * DAY_SECS as usize;
+// chats with less requests are dropped from buckets
+const MAX_CHATS: usize = 100;
+
+pub type Counters = HashMap<String, u64>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Week,
+    All,
+}
+
+impl Period {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Period::Day => "day",
+            Period::Week => "week",
+            Period::All => "all",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "" | "day" | "daily" => Some(Period::Day),
+            "week" | "weekly" => Some(Period::Week),
+            "all" => Some(Period::All),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Counters not saved yet.
+    pending: Mutex<Counters>,
+    /// Counters saved into the day bucket but not the all-time bucket.
+    pending_all: Mutex<Counters>,
+}
+
+impl Stats {
+    pub fn incr(&self, name: impl Into<String>, n: u64) {
+        if n == 0 {
+            return;
+        }
+        *self.pending.lock().entry(name.into()).or_default() += n;
+    }
+
+    /// Save pending counters into today's bucket and the all-time bucket.
+    /// Counters are kept in memory if it fails, and only retried on the
+    /// buckets they are not saved into.
+    pub async fn flush<S: KVStorage<String>>(&self, storage: &S) -> anyhow::Result<()> {
+        self.flush_at(storage, today()).await
+    }
+
+    async fn flush_at<S: KVStorage<String>>(&self, storage: &S, day: u64) -> anyhow::Result<()> {
+        let pending = std::mem::take(&mut *self.pending.lock());
+        let mut all = std::mem::take(&mut *self.pending_all.lock());
+        if !pending.is_empty() {
+            if let Err(e) = merge_into(storage, day_key(day), &pending, Some(DAY_TTL)).await {
+                requeue(&self.pending, pending);
+                requeue(&self.pending_all, all);
+                return Err(e);
+            }
+            for (k, v) in pending {
+                *all.entry(k).or_default() += v;
+            }
+        }
+        if all.is_empty() {
+            return Ok(());
+        }
+        let r = merge_into(storage, format!("{KEY_PREFIX}all"), &all, None).await;
+        if r.is_err() {
+            requeue(&self.pending_all, all);
+        }
+        r
+    }
+
+    /// Load saved counters of the period. Pending counters are not included.
+    pub async fn load<S: KVStorage<String>>(
+        storage: &S,
+        period: Period,
+    ) -> anyhow::Result<Counters> {
+        Self::load_at(storage, period, today()).await
+    }
+
+    async fn load_at<S: KVStorage<String>>(
+        storage: &S,
+        period: Period,
+        day: u64,
+    ) -> anyhow::Result<Counters> {
+        let keys = match period {
+            Period::Day => vec![day_key(day)],
+            Period::Week => (0..7).map(|d| day_key(day.saturating_sub(d))).collect(),
+            Period::All => vec![format!("{KEY_PREFIX}all")],
+        };
+        let mut counters = Counters::new();
+        for key in keys {
+            for (k, v) in read(storage, &key).await? {
+                *counters.entry(k).or_default() += v;
+            }
+        }
+        Ok(counters)
+    }
+}
+
+/// Counters with the prefix sorted by count, with the prefix stripped.
+pub fn group<'a>(counters: &'a Counters, prefix: &str) -> Vec<(&'a str, u64)> {
+    let mut group: Vec<_> = counters
+        .iter()
+        .filter_map(|(k, v)| {
+            k.strip_prefix(prefix)
+                .and_then(|k| k.strip_prefix('|'))
+                .map(|k| (k, *v))
+        })
+        .collect();
+    group.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    group
+}
+
+fn requeue(target: &Mutex<Counters>, counters: Counters) {
+    let mut current = target.lock();
+    for (k, v) in counters {
+        *current.entry(k).or_default() += v;
+    }
+}
+
+fn day_key(day: u64) -> String {
+    format!("{KEY_PREFIX}{day}")
+}
+
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before unix epoch")
+        .as_secs()
+        / DAY_SECS
+}
+
+async fn read<S: KVStorage<String>>(storage: &S, key: &str) -> anyhow::Result<Counters> {
+    Ok(match storage.get(key).await? {
+        Some(v) => serde_json::from_str(&v)?,
+        None => Counters::new(),
+    })
+}
+
+async fn merge_into<S: KVStorage<String>>(
+    storage: &S,
+    key: String,
+    pending: &Counters,
+    ttl: Option<usize>,
+) -> anyhow::Result<()> {
+    let mut counters = read(storage, &key).await?;
+    for (k, v) in pending {
+        *counters.entry(k.clone()).or_default() += v;
+    }
+    let dropped: Vec<_> = group(&counters, "chat")
+        .into_iter()
+        .skip(MAX_CHATS)
+        .map(|(k, _)| format!("chat|{k}"))
+        .collect();
+    for k in dropped {
+        counters.remove(&k);
+    }
+    storage
+        .set(key, serde_json::to_string(&counters)?, ttl)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Future;
+
+    use super::*;
+    use crate::storage::{KeyInfo, SimpleMemStorage};
+
+    #[tokio::test]
+    async fn flush_and_load() {
+        let storage = SimpleMemStorage::default();
+        let stats = Stats::default();
+        stats.incr("sync|ehentai", 1);
+        stats.incr("cache|hit", 2);
+        stats.flush_at(&storage, 10).await.unwrap();
+        stats.incr("sync|ehentai", 2);
+        stats.incr("sync|nhentai", 1);
+        stats.flush_at(&storage, 12).await.unwrap();
+
+        let day = Stats::load_at(&storage, Period::Day, 12).await.unwrap();
+        assert_eq!(day["sync|ehentai"], 2);
+        assert!(!day.contains_key("cache|hit"));
+        let week = Stats::load_at(&storage, Period::Week, 12).await.unwrap();
+        assert_eq!(week["sync|ehentai"], 3);
+        assert_eq!(week["cache|hit"], 2);
+        // out of the week
+        let week = Stats::load_at(&storage, Period::Week, 17).await.unwrap();
+        assert!(!week.contains_key("cache|hit"));
+        let all = Stats::load_at(&storage, Period::All, 100).await.unwrap();
+        assert_eq!(group(&all, "sync"), vec![("ehentai", 3), ("nhentai", 1)]);
+    }
+
+    /// Storage failing to set keys with the prefix.
+    #[derive(Default)]
+    struct FailingStorage {
+        inner: SimpleMemStorage,
+        fail: Mutex<Option<String>>,
+    }
+
+    impl KVStorage<String> for FailingStorage {
+        type GetFuture<'a> = impl Future<Output = anyhow::Result<Option<String>>> + Send where Self: 'a;
+        fn get<'a>(&'a self, key: &'a str) -> Self::GetFuture<'_> {
+            self.inner.get(key)
+        }
+
+        type SetFuture<'a> = impl Future<Output = anyhow::Result<()>> + Send where Self: 'a;
+        fn set(&self, key: String, value: String, ttl: Option<usize>) -> Self::SetFuture<'_> {
+            let fail = matches!(&*self.fail.lock(), Some(p) if key.starts_with(p.as_str()));
+            async move {
+                if fail {
+                    return Err(anyhow::anyhow!("set {key} failed"));
+                }
+                self.inner.set(key, value, ttl).await
+            }
+        }
+
+        type DeleteFuture<'a> = impl Future<Output = anyhow::Result<()>> + Send where Self: 'a;
+        fn delete<'a>(&'a self, key: &'a str) -> Self::DeleteFuture<'_> {
+            self.inner.delete(key)
+        }
+
+        type ScanFuture<'a> = impl Future<Output = anyhow::Result<Vec<KeyInfo>>> + Send where Self: 'a;
+        fn scan<'a>(&'a self, prefix: &'a str) -> Self::ScanFuture<'_> {
+            self.inner.scan(prefix)
+        }
+    }
+
+    #[tokio::test]
+    async fn flush_retry() {
+        let storage = FailingStorage::default();
+        let stats = Stats::default();
+        stats.incr("sync|ehentai", 1);
+        *storage.fail.lock() = Some(format!("{KEY_PREFIX}all"));
+        assert!(stats.flush_at(&storage, 10).await.is_err());
+        stats.incr("sync|ehentai", 1);
+        *storage.fail.lock() = None;
+        stats.flush_at(&storage, 10).await.unwrap();
+
+        // the day bucket is not counted twice
+        let day = Stats::load_at(&storage, Period::Day, 10).await.unwrap();
+        assert_eq!(day["sync|ehentai"], 2);
+        let all = Stats::load_at(&storage, Period::All, 10).await.unwrap();
+        assert_eq!(all["sync|ehentai"], 2);
+
+        // nothing is saved if the day bucket fails
+        stats.incr("sync|ehentai", 1);
+        *storage.fail.lock() = Some(KEY_PREFIX.to_string());
+        assert!(stats.flush_at(&storage, 10).await.is_err());
+        *storage.fail.lock() = None;
+        stats.flush_at(&storage, 10).await.unwrap();
+        let all = Stats::load_at(&storage, Period::All, 10).await.unwrap();
+        assert_eq!(all["sync|ehentai"], 3);
+    }
+
+    #[test]
+    fn period() {
+        assert_eq!(Period::parse(""), Some(Period::Day));
+        assert_eq!(Period::parse("weekly"), Some(Period::Week));
+        assert_eq!(Period::parse("month"), None);
+    }
+}
@@ -2,11 +2,12 @@ use std::{sync::Arc, time::Duration};
 
 use cloudflare_kv_proxy::{Client, ClientError, NotFoundMapping};
 use futures::Future;
+use reqwest::{header, Response};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::config;
 
-use super::KVStorage;
+use super::{KVStorage, KeyInfo};
 
 const CONFIG_KEY: &str = "worker_kv";
 
@@ -19,7 +20,34 @@ pub struct CFConfig {
 }
 
 #[derive(Clone, Debug)]
-pub struct CFStorage(Arc<Client>);
+pub struct CFStorage {
+    client: Arc<Client>,
+    // cloudflare-kv-proxy does not support listing, so we do it with a raw client.
+    // Note: listing requires the worker in `worker/kv_proxy.js`.
+    endpoint: Arc<String>,
+    // path of the endpoint, which is a part of keys seen by the worker
+    key_base: Arc<String>,
+    raw_client: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListResult {
+    #[serde(with = "serde_with::json::nested")]
+    result: ListPage,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListPage {
+    keys: Vec<ListKey>,
+    list_complete: bool,
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListKey {
+    name: String,
+    expiration: Option<u64>,
+}
 
 impl CFStorage {
     pub fn new<T: Into<String>, E: Into<String>>(
@@ -28,13 +56,43 @@ impl CFStorage {
         cache_size: usize,
         expire: Duration,
     ) -> Result<Self, ClientError> {
-        Ok(Self(Arc::new(Client::new(
-            endpoint, token, cache_size, expire,
-        )?)))
+        let mut endpoint: String = endpoint.into();
+        if !endpoint.ends_with('/') {
+            endpoint.push('/');
+        }
+        let token: String = token.into();
+        let key_base = reqwest::Url::parse(&endpoint)
+            .map(|u| u.path().trim_start_matches('/').to_string())
+            .unwrap_or_default();
+
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            header::HeaderValue::from_str(&token)?,
+        );
+        let raw_client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?;
+
+        Ok(Self {
+            client: Arc::new(Client::new(endpoint.clone(), token, cache_size, expire)?),
+            endpoint: Arc::new(endpoint),
+            key_base: Arc::new(key_base),
+            raw_client,
+        })
     }
 
     pub fn new_from_config() -> anyhow::Result<Self> {
-        let config: CFConfig = config::parse(CONFIG_KEY)?
+        Self::from_config(config::parse(CONFIG_KEY)?)
+    }
+
+    /// Build from the config file of another deployment, for migrating cache.
+    pub fn new_from_config_file(path: &str) -> anyhow::Result<Self> {
+        Self::from_config(config::parse_file(path, CONFIG_KEY)?)
+    }
+
+    fn from_config(config: Option<CFConfig>) -> anyhow::Result<Self> {
+        let config = config
             .ok_or_else(|| anyhow::anyhow!("cloudflare worker config(key: worker_kv) not found"))?;
         Self::new(
             config.endpoint,
@@ -44,6 +102,25 @@ impl CFStorage {
         )
         .map_err(Into::into)
     }
+
+    async fn list_page(&self, prefix: &str, cursor: Option<&str>) -> anyhow::Result<ListPage> {
+        let prefix = format!("{}{prefix}", self.key_base);
+        let mut query = vec![("list", "1"), ("prefix", &prefix)];
+        if let Some(cursor) = cursor {
+            query.push(("cursor", cursor));
+        }
+        let page = self
+            .raw_client
+            .get(self.endpoint.as_str())
+            .query(&query)
+            .send()
+            .await
+            .and_then(Response::error_for_status)?
+            .json::<ListResult>()
+            .await?
+            .result;
+        Ok(page)
+    }
 }
 
 impl<T> KVStorage<T> for CFStorage
@@ -53,7 +130,7 @@ where
     type GetFuture<'a> = impl Future<Output = anyhow::Result<Option<T>>> where Self: 'a;
     fn get<'a>(&'a self, key: &'a str) -> Self::GetFuture<'_> {
         async move {
-            self.0
+            self.client
                 .get(key)
                 .await
                 .map_not_found_to_option()
@@ -62,12 +139,48 @@ where
     }
 
     type SetFuture<'a> = impl Future<Output = anyhow::Result<()>> where Self: 'a;
-    fn set<'a>(&self, key: String, value: T, _expire_ttl: Option<usize>) -> Self::SetFuture<'_> {
-        async move { self.0.put(&key, &value).await.map_err(Into::into) }
+    fn set<'a>(&self, key: String, value: T, expire_ttl: Option<usize>) -> Self::SetFuture<'_> {
+        async move {
+            match expire_ttl {
+                Some(ttl) => {
+                    self.client
+                        .put_with_ttl(&key, &value, Duration::from_secs(ttl as u64))
+                        .await
+                }
+                None => self.client.put(&key, &value).await,
+            }
+            .map_err(Into::into)
+        }
     }
 
     type DeleteFuture<'a> = impl Future<Output = anyhow::Result<()>> where Self: 'a;
     fn delete<'a>(&'a self, key: &'a str) -> Self::DeleteFuture<'_> {
-        async move { self.0.delete(key).await.map_err(Into::into) }
+        async move { self.client.delete(key).await.map_err(Into::into) }
+    }
+
+    type ScanFuture<'a> = impl Future<Output = anyhow::Result<Vec<KeyInfo>>> where Self: 'a;
+    fn scan<'a>(&'a self, prefix: &'a str) -> Self::ScanFuture<'_> {
+        async move {
+            let mut keys = Vec::new();
+            let mut cursor = None;
+            loop {
+                let page = self.list_page(prefix, cursor.as_deref()).await?;
+                keys.extend(page.keys.into_iter().map(|k| {
+                    KeyInfo {
+                        key: k
+                            .name
+                            .strip_prefix(self.key_base.as_str())
+                            .map(ToString::to_string)
+                            .unwrap_or(k.name),
+                        expire_at: k.expiration,
+                    }
+                }));
+                if page.list_complete || page.cursor.is_none() {
+                    break;
+                }
+                cursor = page.cursor;
+            }
+            Ok(keys)
+        }
     }
 }
@@ -0,0 +1,128 @@
+//! Export and import storage entries as JSON Lines.
+//! It makes migrating between storage backends possible.
+use std::{
+    io::{BufRead, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::KVStorage;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpEntry {
+    pub key: String,
+    pub value: String,
+    /// Expiration in unix seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_at: Option<u64>,
+}
+
+#[inline]
+fn now_sec() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before unix epoch")
+        .as_secs()
+}
+
+/// Write all entries with given prefix to writer, one json object per line.
+/// Returns the count of exported entries.
+pub async fn export<S, W>(storage: &S, prefix: &str, mut writer: W) -> anyhow::Result<usize>
+where
+    S: KVStorage<String>,
+    W: Write,
+{
+    let keys = storage.scan(prefix).await?;
+    let mut count = 0;
+    for info in keys {
+        // the key may be expired or deleted during scanning
+        let value = match storage.get(&info.key).await? {
+            Some(v) => v,
+            None => continue,
+        };
+        let entry = DumpEntry {
+            key: info.key,
+            value,
+            expire_at: info.expire_at,
+        };
+        serde_json::to_writer(&mut writer, &entry)?;
+        writer.write_all(b"\n")?;
+        count += 1;
+    }
+    writer.flush()?;
+    tracing::info!("[dump] exported {count} entries with prefix {prefix:?}");
+    Ok(count)
+}
+
+/// Read entries from reader and write them to storage.
+/// Expired entries are skipped, and the remaining ttl is kept.
+/// Returns the count of imported entries.
+pub async fn import<S, R>(storage: &S, reader: R) -> anyhow::Result<usize>
+where
+    S: KVStorage<String>,
+    R: BufRead,
+{
+    let now = now_sec();
+    let mut count = 0;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: DumpEntry = serde_json::from_str(&line)?;
+        let ttl = match entry.expire_at {
+            Some(t) if t <= now => {
+                tracing::debug!("[dump] skip expired key {}", entry.key);
+                continue;
+            }
+            Some(t) => Some((t - now) as usize),
+            None => None,
+        };
+        storage.set(entry.key, entry.value, ttl).await?;
+        count += 1;
+    }
+    tracing::info!("[dump] imported {count} entries");
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SimpleMemStorage;
+
+    #[tokio::test]
+    async fn export_import() {
+        let src = SimpleMemStorage::default();
+        src.set("a|1".to_string(), "v1".to_string(), None)
+            .await
+            .unwrap();
+        src.set("a|2".to_string(), "v2".to_string(), None)
+            .await
+            .unwrap();
+        src.set("b|1".to_string(), "v3".to_string(), None)
+            .await
+            .unwrap();
+
+        let mut buf = Vec::new();
+        assert_eq!(export(&src, "a|", &mut buf).await.unwrap(), 2);
+
+        let dst = SimpleMemStorage::default();
+        assert_eq!(import(&dst, buf.as_slice()).await.unwrap(), 2);
+        assert_eq!(dst.get("a|1").await.unwrap(), Some("v1".to_string()));
+        assert_eq!(dst.get("a|2").await.unwrap(), Some("v2".to_string()));
+        assert_eq!(dst.get("b|1").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn skip_expired() {
+        let data = format!(
+            "{}\n{}\n",
+            r#"{"key":"k1","value":"v1","expire_at":1}"#, r#"{"key":"k2","value":"v2"}"#
+        );
+        let dst = SimpleMemStorage::default();
+        assert_eq!(import(&dst, data.as_bytes()).await.unwrap(), 1);
+        assert_eq!(dst.get("k1").await.unwrap(), None);
+        assert_eq!(dst.get("k2").await.unwrap(), Some("v2".to_string()));
+    }
+}
@@ -4,7 +4,7 @@ use futures::Future;
 use hashlink::LruCache;
 use parking_lot::Mutex;
 
-use super::KVStorage;
+use super::{KVStorage, KeyInfo};
 
 #[derive(Clone, Debug)]
 pub struct LruStorage(Arc<Mutex<LruCache<String, String>>>);
@@ -38,4 +38,18 @@ impl KVStorage<String> for LruStorage {
         self.0.lock().remove(key);
         async move { Ok(()) }
     }
+
+    type ScanFuture<'a> = impl Future<Output = anyhow::Result<Vec<KeyInfo>>> where Self: 'a;
+    fn scan<'a>(&'a self, prefix: &'a str) -> Self::ScanFuture<'_> {
+        let keys = self
+            .0
+            .lock()
+            .iter()
+            .map(|(k, _)| k)
+            .filter(|k| k.starts_with(prefix))
+            .cloned()
+            .map(KeyInfo::new)
+            .collect();
+        async move { Ok(keys) }
+    }
 }
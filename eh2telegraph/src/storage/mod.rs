@@ -3,8 +3,28 @@ use parking_lot::RwLock;
 use std::{collections::HashMap, sync::Arc};
 
 pub mod cloudflare_kv;
+pub mod dump;
 pub mod lru;
 
+/// Key info returned by `KVStorage::scan`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyInfo {
+    pub key: String,
+    /// Expiration in unix seconds, None if the key never expires or the
+    /// backend does not track it.
+    pub expire_at: Option<u64>,
+}
+
+impl KeyInfo {
+    #[inline]
+    pub fn new(key: String) -> Self {
+        Self {
+            key,
+            expire_at: None,
+        }
+    }
+}
+
 pub trait KVStorage<V> {
     type GetFuture<'a>: Future<Output = anyhow::Result<Option<V>>> + Send
     where
@@ -20,6 +40,12 @@ pub trait KVStorage<V> {
     where
         Self: 'a;
     fn delete<'a>(&'a self, key: &'a str) -> Self::DeleteFuture<'_>;
+
+    type ScanFuture<'a>: Future<Output = anyhow::Result<Vec<KeyInfo>>> + Send
+    where
+        Self: 'a;
+    /// Enumerate all keys starting with the given prefix.
+    fn scan<'a>(&'a self, prefix: &'a str) -> Self::ScanFuture<'_>;
 }
 
 #[derive(Default, Clone, Debug)]
@@ -54,4 +80,17 @@ impl KVStorage<String> for SimpleMemStorage {
         self.0.write().remove(key);
         async move { Ok(()) }
     }
+
+    type ScanFuture<'a> = impl Future<Output = anyhow::Result<Vec<KeyInfo>>> where Self: 'a;
+    fn scan<'a>(&'a self, prefix: &'a str) -> Self::ScanFuture<'_> {
+        let keys = self
+            .0
+            .read()
+            .keys()
+            .filter(|k| k.starts_with(prefix))
+            .cloned()
+            .map(KeyInfo::new)
+            .collect();
+        async move { Ok(keys) }
+    }
 }
@@ -5,6 +5,8 @@ use std::future::Future;
 use futures::FutureExt;
 use tokio::sync::oneshot;
 
+use crate::metrics::BUFFERED_QUEUE;
+
 /// We define a AsyncStream to replace futures::Stream since we don't want to implement
 /// poll_next nor using async_stream.
 /// Although we use GAT, we don't want the future to capture self's ref. We did like
@@ -54,6 +56,15 @@ where
     }
 }
 
+impl<St> Drop for Buffered<St>
+where
+    St: AsyncStream,
+{
+    fn drop(&mut self) {
+        BUFFERED_QUEUE.sub(self.queue.len() as i64);
+    }
+}
+
 impl<St> fmt::Debug for Buffered<St>
 where
     St: AsyncStream + fmt::Debug,
@@ -97,12 +108,15 @@ where
                         let _ = tx.send(f.await);
                     });
                     self.queue.push_back(rx);
+                    BUFFERED_QUEUE.inc();
                 }
                 None => break,
             }
         }
-        self.queue
-            .pop_front()
-            .map(|x| x.map(|xx| xx.expect("oneshot tx dropped which is unexpected")))
+        let rx = self.queue.pop_front();
+        if rx.is_some() {
+            BUFFERED_QUEUE.dec();
+        }
+        rx.map(|x| x.map(|xx| xx.expect("oneshot tx dropped which is unexpected")))
     }
 }
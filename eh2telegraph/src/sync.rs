@@ -1,10 +1,26 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use hashlink::LruCache;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
 use crate::{
+    archive::{self, CbzWriter, ExportError},
     buffer::{DataSized, ImageBuffer},
     collector::{
-        AlbumMeta, Collector, ImageData, ImageMeta, Param, Registry, URL_FROM_TEXT_RE,
-        URL_FROM_URL_RE,
+        e_hentai::EHCollector, exhentai::EXCollector, nhentai::NHCollector, AlbumMeta, Collector,
+        ImageData, ImageMeta, InputError, Param, Registry, URL_FROM_TEXT_RE, URL_FROM_URL_RE,
     },
     http_proxy::ProxiedClient,
+    metrics,
+    searcher::local::{dhash, IndexedGallery, PHashIndex},
+    stats::{Counters, Period, Stats},
     storage::{cloudflare_kv::CFStorage, KVStorage},
     stream::{AsyncStream, Buffered},
     telegraph::{
@@ -14,11 +30,44 @@ use crate::{
     util::match_first_group,
 };
 
-const ERR_THRESHOLD: usize = 10;
+pub(crate) const ERR_THRESHOLD: usize = 10;
 const BATCH_LEN_THRESHOLD: usize = 20;
 const BATCH_SIZE_THRESHOLD: usize = 5 * 1024 * 1024;
 const DEFAULT_CONCURRENT: usize = 20;
+// max records kept in memory for searching, least recently synced ones are dropped
+const MAX_RECORDS: usize = 50_000;
+// records are saved in chunks with keys `records|{n}`, so loading them takes a few requests
+const RECORDS_KEY_PREFIX: &str = "records|";
+const RECORDS_CHUNK_SIZE: usize = 5_000;
+
+/// Route url to the matching collector.
+/// `$body` is evaluated with the collector type bound to `$c` and url path bound to `$path`.
+macro_rules! route {
+    ($url: expr, $c: ident, $path: ident => $body: expr) => {{
+        let u = reqwest::Url::parse($url).map_err(|_| InputError("Invalid url".to_string()))?;
+        let host = u.host_str().unwrap_or_default();
+        let $path = u.path().to_string();
+
+        match host {
+            "e-hentai.org" => {
+                type $c = EHCollector;
+                $body
+            }
+            "nhentai.to" | "nhentai.net" => {
+                type $c = NHCollector;
+                $body
+            }
+            "exhentai.org" => {
+                type $c = EXCollector;
+                $body
+            }
+            _ => Err(InputError("no matching collector".to_string()).into()),
+        }
+    }};
+}
 
+/// Errors of sync are returned as `UploadError<anyhow::Error>` so they can be
+/// downcasted without knowing the collector.
 #[derive(thiserror::Error, Debug)]
 pub enum UploadError<SE> {
     #[error("stream error {0}")]
@@ -37,6 +86,89 @@ pub struct Synchronizer<C = CFStorage> {
 
     registry: Registry,
     cache: C,
+    // cache key -> record with title, used for searching synced galleries
+    records: Mutex<LruCache<String, Arc<CacheRecord>>>,
+    // records are changed since last saved
+    records_dirty: AtomicBool,
+    // saved records are loaded, so saving does not drop them
+    records_loaded: AtomicBool,
+    phash_index: Option<PHashIndex<C>>,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    stats: Option<Arc<Stats>>,
+}
+
+/// Cache value of a synced gallery, saved as JSON.
+/// Cache values written by old versions are plain telegraph urls, and they are
+/// still readable. Old versions read JSON values as urls, so rolling back
+/// requires purging records synced by this version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheRecord {
+    /// Telegraph url.
+    pub url: String,
+    #[serde(default)]
+    pub title: String,
+    /// Original gallery link.
+    #[serde(default)]
+    pub link: String,
+}
+
+/// Result of a sync.
+#[derive(Debug, Clone)]
+pub struct Synced {
+    /// Telegraph url.
+    pub url: String,
+    /// Meta of the gallery. None if it is synced from cache.
+    pub meta: Option<AlbumMeta>,
+    /// Cover image url of the telegraph page.
+    pub cover: Option<String>,
+}
+
+impl CacheRecord {
+    pub fn parse(value: String) -> Self {
+        if value.starts_with('{') {
+            if let Ok(r) = serde_json::from_str(&value) {
+                return r;
+            }
+        }
+        Self {
+            url: value,
+            title: String::new(),
+            link: String::new(),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("unable to serialize cache record")
+    }
+}
+
+/// Upload progress of a sync.
+/// Total is the count of images in the gallery, and it is 0 before fetched.
+#[derive(Debug, Default)]
+pub struct Progress {
+    pub total: AtomicUsize,
+    pub uploaded: AtomicUsize,
+}
+
+impl Progress {
+    /// Returns (uploaded, total).
+    pub fn get(&self) -> (usize, usize) {
+        (
+            self.uploaded.load(Ordering::Relaxed),
+            self.total.load(Ordering::Relaxed),
+        )
+    }
+}
+
+tokio::task_local! {
+    static PROGRESS: Arc<Progress>;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
 }
 
 impl<CACHE> Synchronizer<CACHE>
@@ -59,6 +191,13 @@ where
             cache_ttl: None,
             registry,
             cache,
+            records: Mutex::new(LruCache::new(MAX_RECORDS)),
+            records_dirty: AtomicBool::new(false),
+            records_loaded: AtomicBool::new(false),
+            phash_index: None,
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            stats: None,
         }
     }
 
@@ -78,11 +217,268 @@ where
         self
     }
 
+    /// Save perceptual hashes of synced images to the index.
+    pub fn with_phash_index(mut self, index: PHashIndex<CACHE>) -> Self {
+        self.phash_index = Some(index);
+        self
+    }
+
+    /// Count syncs, cache hits, uploads and failures.
+    pub fn with_stats(mut self, stats: Arc<Stats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    #[inline]
+    fn incr(&self, name: &str, n: u64) {
+        if let Some(stats) = &self.stats {
+            stats.incr(name, n);
+        }
+    }
+
     pub async fn delete_cache(&self, key: &str) -> anyhow::Result<()> {
+        if self.records.lock().remove(key).is_some() {
+            self.records_dirty.store(true, Ordering::Relaxed);
+        }
         self.cache.delete(key).await
     }
 
+    /// Cache key of the given collector and path.
+    #[inline]
+    pub fn cache_key<C: Collector>(path: &str) -> String {
+        format!("{}|{}", C::name(), path)
+    }
+
+    /// Get cached telegraph url of the given collector and path.
+    pub async fn get_cache<C: Collector>(&self, path: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.get_record::<C>(path).await?.map(|r| r.url))
+    }
+
+    /// Get cache record of the given collector and path.
+    pub async fn get_record<C: Collector>(
+        &self,
+        path: &str,
+    ) -> anyhow::Result<Option<CacheRecord>> {
+        Ok(self
+            .cache
+            .get(&Self::cache_key::<C>(path))
+            .await?
+            .map(CacheRecord::parse))
+    }
+
+    /// Delete cache of the given collector and path.
+    pub async fn purge_cache<C: Collector>(&self, path: &str) -> anyhow::Result<()> {
+        self.delete_cache(&Self::cache_key::<C>(path)).await
+    }
+
+    /// Sync the gallery url with the matching collector.
+    pub async fn sync_url(&self, url: &str, force: bool) -> anyhow::Result<Synced> {
+        route!(url, Col, path => {
+            tracing::info!("[registry] sync {} for path {}", Col::name(), path);
+            self.sync_with::<Col>(path, force).await
+        })
+    }
+
+    /// Sync the gallery url and report the upload progress.
+    pub async fn sync_url_with_progress(
+        &self,
+        url: &str,
+        force: bool,
+        progress: Arc<Progress>,
+    ) -> anyhow::Result<Synced> {
+        PROGRESS.scope(progress, self.sync_url(url, force)).await
+    }
+
+    /// Export the gallery url as a CBZ archive. Cache is not used.
+    pub async fn export_url<W>(
+        &self,
+        url: &str,
+        writer: CbzWriter<W>,
+    ) -> anyhow::Result<(AlbumMeta, W)>
+    where
+        W: std::io::Write + std::io::Seek + Send + 'static,
+    {
+        route!(url, Col, path => self.export::<Col, W>(path, writer).await)
+    }
+
+    /// Get cache record of the gallery url.
+    pub async fn get_record_url(&self, url: &str) -> anyhow::Result<Option<CacheRecord>> {
+        route!(url, Col, path => self.get_record::<Col>(&path).await)
+    }
+
+    /// Delete cache of the gallery url.
+    pub async fn purge_url(&self, url: &str) -> anyhow::Result<()> {
+        route!(url, Col, path => self.purge_cache::<Col>(&path).await)
+    }
+
+    /// Load records saved by `save_records` for searching by title.
+    /// Returns the count of loaded records.
+    /// If they are never saved, records are rebuilt from cache of each gallery once,
+    /// which takes a request per gallery.
+    pub async fn load_records(&self) -> anyhow::Result<usize> {
+        let mut count = 0;
+        let mut chunks = 0;
+        while let Some(value) = self
+            .cache
+            .get(&format!("{RECORDS_KEY_PREFIX}{chunks}"))
+            .await?
+        {
+            let chunk: Vec<(String, CacheRecord)> = serde_json::from_str(&value)?;
+            for (key, record) in chunk {
+                if self.index_record(key, record) {
+                    count += 1;
+                }
+            }
+            chunks += 1;
+        }
+        if chunks == 0 {
+            for prefix in [
+                Self::cache_key::<EHCollector>(""),
+                Self::cache_key::<EXCollector>(""),
+                Self::cache_key::<NHCollector>(""),
+            ] {
+                count += self.scan_records(&prefix).await?;
+            }
+        }
+        self.records_loaded.store(true, Ordering::Relaxed);
+        if chunks == 0 {
+            self.save_records().await?;
+        }
+        tracing::info!("[cache] loaded {count} records");
+        Ok(count)
+    }
+
+    async fn scan_records(&self, prefix: &str) -> anyhow::Result<usize> {
+        let keys = self.cache.scan(prefix).await?;
+        let mut count = 0;
+        for info in keys.into_iter().take(MAX_RECORDS) {
+            if let Some(value) = self.cache.get(&info.key).await? {
+                if self.index_record(info.key, CacheRecord::parse(value)) {
+                    count += 1;
+                }
+            }
+        }
+        tracing::info!("[cache] rebuilt {count} records with prefix {prefix}");
+        Ok(count)
+    }
+
+    /// Save records for searching if they are changed. Nothing is saved before
+    /// `load_records` succeeds, since saved records would be overwritten.
+    /// Least recently synced ones come first, so the order is kept after loading.
+    pub async fn save_records(&self) -> anyhow::Result<()> {
+        if !self.records_loaded.load(Ordering::Relaxed)
+            || !self.records_dirty.swap(false, Ordering::Relaxed)
+        {
+            return Ok(());
+        }
+        let records: Vec<_> = self
+            .records
+            .lock()
+            .iter()
+            .map(|(k, r)| (k.clone(), CacheRecord::clone(r)))
+            .collect();
+        let r: anyhow::Result<usize> = async {
+            let mut chunks = 0;
+            for chunk in records.chunks(RECORDS_CHUNK_SIZE) {
+                let value = serde_json::to_string(chunk)?;
+                self.cache
+                    .set(format!("{RECORDS_KEY_PREFIX}{chunks}"), value, None)
+                    .await?;
+                chunks += 1;
+            }
+            // loading stops at the first missing chunk
+            self.cache
+                .delete(&format!("{RECORDS_KEY_PREFIX}{chunks}"))
+                .await?;
+            Ok(chunks)
+        }
+        .await;
+        match r {
+            Ok(chunks) => {
+                tracing::info!("[cache] saved {} records in {chunks} chunks", records.len());
+                Ok(())
+            }
+            Err(e) => {
+                self.records_dirty.store(true, Ordering::Relaxed);
+                Err(e)
+            }
+        }
+    }
+
+    /// Search synced galleries whose title contains all keywords.
+    /// Titles with keywords as whole words come first, then shorter titles.
+    pub fn search_records(&self, keywords: &str, limit: usize) -> Vec<Arc<CacheRecord>> {
+        let keywords: Vec<_> = keywords.split_whitespace().map(str::to_lowercase).collect();
+        if keywords.is_empty() {
+            return Vec::new();
+        }
+        let records: Vec<_> = self.records.lock().iter().map(|(_, r)| r.clone()).collect();
+        rank_records(records, &keywords, limit)
+    }
+
+    // Legacy records without title are not indexed.
+    fn index_record(&self, key: String, record: CacheRecord) -> bool {
+        if record.title.is_empty() {
+            return false;
+        }
+        self.records.lock().insert(key, Arc::new(record));
+        self.records_dirty.store(true, Ordering::Relaxed);
+        true
+    }
+
+    pub fn stats(&self) -> Option<&Arc<Stats>> {
+        self.stats.as_ref()
+    }
+
+    /// Save pending usage counters to cache storage.
+    pub async fn flush_stats(&self) -> anyhow::Result<()> {
+        match &self.stats {
+            Some(stats) => stats.flush(&self.cache).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Flush and load usage counters of the period.
+    pub async fn load_stats(&self, period: Period) -> anyhow::Result<Counters> {
+        self.flush_stats().await?;
+        Stats::load(&self.cache, period).await
+    }
+
+    /// Cache hits and misses since start.
+    pub fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.cache_hits.load(Ordering::Relaxed),
+            misses: self.cache_misses.load(Ordering::Relaxed),
+        }
+    }
+
     pub async fn sync<C: Collector>(&self, path: String) -> anyhow::Result<String>
+    where
+        Registry: Param<C>,
+        C::FetchError: Into<anyhow::Error> + Send + 'static,
+        C::StreamError:
+            Into<anyhow::Error> + std::fmt::Debug + std::fmt::Display + Send + Sync + 'static,
+        C::ImageStream: Send + 'static,
+        <C::ImageStream as AsyncStream>::Future: Send + 'static,
+    {
+        self.sync_with::<C>(path, false).await.map(|s| s.url)
+    }
+
+    /// Sync with cache bypassed. The cache will be updated with the new result.
+    pub async fn resync<C: Collector>(&self, path: String) -> anyhow::Result<String>
+    where
+        Registry: Param<C>,
+        C::FetchError: Into<anyhow::Error> + Send + 'static,
+        C::StreamError:
+            Into<anyhow::Error> + std::fmt::Debug + std::fmt::Display + Send + Sync + 'static,
+        C::ImageStream: Send + 'static,
+        <C::ImageStream as AsyncStream>::Future: Send + 'static,
+    {
+        self.sync_with::<C>(path, true).await.map(|s| s.url)
+    }
+
+    /// Sync and return the meta of the gallery along with the page.
+    pub async fn sync_with<C: Collector>(&self, path: String, force: bool) -> anyhow::Result<Synced>
     where
         Registry: Param<C>,
         C::FetchError: Into<anyhow::Error> + Send + 'static,
@@ -92,30 +488,111 @@ where
         <C::ImageStream as AsyncStream>::Future: Send + 'static,
     {
         // check cache
-        let cache_key = format!("{}|{}", C::name(), path);
-        if let Ok(Some(v)) = self.cache.get(&cache_key).await {
+        let cache_key = Self::cache_key::<C>(&path);
+        if force {
+            tracing::info!("[cache] bypass key {cache_key}");
+        } else if let Ok(Some(v)) = self.cache.get(&cache_key).await {
             tracing::info!("[cache] hit key {cache_key}");
-            return Ok(v);
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+            self.incr("cache|hit", 1);
+            let record = CacheRecord::parse(v);
+            let url = record.url.clone();
+            self.index_record(cache_key, record);
+            return Ok(Synced {
+                url,
+                meta: None,
+                cover: None,
+            });
+        } else {
+            tracing::info!("[cache] miss key {cache_key}");
+            self.cache_misses.fetch_add(1, Ordering::Relaxed);
+            self.incr("cache|miss", 1);
         }
-        tracing::info!("[cache] miss key {cache_key}");
 
-        let collector: &C = self.registry.get();
-        let (meta, stream) = collector.fetch(path).await.map_err(Into::into)?;
-        let page = self
-            .sync_stream(meta, stream)
-            .await
-            .map_err(anyhow::Error::from)?;
+        let start = Instant::now();
+        let in_flight = metrics::GaugeGuard::new(&metrics::SYNC_IN_FLIGHT);
+        let r: anyhow::Result<_> = async {
+            let collector: &C = self.registry.get();
+            let (meta, stream) = collector.fetch(path).await.map_err(|e| {
+                self.incr("failure|fetch", 1);
+                e.into()
+            })?;
+            let synced_meta = meta.clone();
+            let page = self.sync_stream(meta, stream).await.map_err(|e| {
+                let e: UploadError<anyhow::Error> = match e {
+                    UploadError::Stream(e) => {
+                        self.incr("failure|download", 1);
+                        UploadError::Stream(e.into())
+                    }
+                    UploadError::Reqwest(e) => {
+                        self.incr("failure|telegraph", 1);
+                        UploadError::Reqwest(e)
+                    }
+                };
+                anyhow::Error::from(e)
+            })?;
+            Ok((page, synced_meta))
+        }
+        .await;
+        drop(in_flight);
+        metrics::SYNC_DURATION
+            .with_label_values(&[C::name(), if r.is_ok() { "ok" } else { "error" }])
+            .observe(start.elapsed().as_secs_f64());
+        let (page, synced_meta) = r?;
+        self.incr(&format!("sync|{}", C::name()), 1);
 
         // set cache
+        let record = CacheRecord {
+            url: page.url,
+            title: page.title,
+            link: synced_meta.link.clone(),
+        };
         let _ = self
             .cache
             .set(
-                cache_key,
-                page.url.clone(),
+                cache_key.clone(),
+                record.encode(),
                 Some(self.cache_ttl.unwrap_or(Self::DEFAULT_CACHE_TTL)),
             )
             .await;
-        Ok(page.url)
+        let url = record.url.clone();
+        self.index_record(cache_key, record);
+        Ok(Synced {
+            url,
+            meta: Some(synced_meta),
+            cover: page.image_url,
+        })
+    }
+
+    /// Fetch the gallery and write it into the archive.
+    pub async fn export<C: Collector, W>(
+        &self,
+        path: String,
+        writer: CbzWriter<W>,
+    ) -> anyhow::Result<(AlbumMeta, W)>
+    where
+        Registry: Param<C>,
+        C::FetchError: Into<anyhow::Error> + Send + 'static,
+        C::StreamError:
+            Into<anyhow::Error> + std::fmt::Debug + std::fmt::Display + Send + Sync + 'static,
+        C::ImageStream: Send + 'static,
+        <C::ImageStream as AsyncStream>::Future: Send + 'static,
+        W: std::io::Write + std::io::Seek + Send + 'static,
+    {
+        let collector: &C = self.registry.get();
+        let (meta, stream) = collector.fetch(path).await.map_err(Into::into)?;
+        let inner = archive::export_stream(
+            &meta,
+            stream,
+            writer,
+            self.limit.unwrap_or(DEFAULT_CONCURRENT),
+        )
+        .await
+        .map_err(|e| match e {
+            ExportError::Stream(e) => UploadError::<anyhow::Error>::Stream(e.into()).into(),
+            ExportError::Archive(e) => anyhow::Error::from(e),
+        })?;
+        Ok((meta, inner))
     }
 
     pub async fn sync_stream<S, SE>(
@@ -128,6 +605,7 @@ where
         S: AsyncStream<Item = Result<(ImageMeta, ImageData), SE>>,
         S::Future: Send + 'static,
     {
+        let _ = PROGRESS.try_with(|p| p.total.store(stream.size_hint().0, Ordering::Relaxed));
         let buffered_stream = Buffered::new(stream, self.limit.unwrap_or(DEFAULT_CONCURRENT));
         let r = self.inner_sync_stream(meta, buffered_stream).await;
         match &r {
@@ -151,6 +629,7 @@ where
     {
         let mut err_count = 0;
         let mut uploaded = Vec::new();
+        let mut hashes = Vec::new();
 
         let mut buffer = ImageBuffer::new();
 
@@ -203,8 +682,24 @@ where
                 .into_iter()
                 .map(|(a, b)| (a, b.as_ref().to_owned()))
                 .unzip::<_, _, Vec<_>, Vec<_>>();
+            // hash images while uploading them
+            let hashing = self.phash_index.as_ref().map(|_| {
+                let data = data.clone();
+                tokio::task::spawn_blocking(move || {
+                    data.iter().filter_map(|d| dhash(d)).collect::<Vec<_>>()
+                })
+            });
             let medium = self.tg.upload(data).await?;
             err_count = 0;
+            self.incr("upload|images", image_count as u64);
+            let _ = PROGRESS.try_with(|p| p.uploaded.fetch_add(image_count, Ordering::Relaxed));
+            self.incr("upload|bytes", size as u64);
+            if let Some(hashing) = hashing {
+                match hashing.await {
+                    Ok(h) => hashes.extend(h),
+                    Err(e) => tracing::error!("[phash] hashing task fail: {e:?}"),
+                }
+            }
 
             // 3. add to uploaded
             tracing::debug!("upload {image_count} images with size {size}, medium: {medium:?}");
@@ -220,9 +715,10 @@ where
         content.push(Node::new_p_text("Generated by eh2telegraph."));
         content.push(Node::new_p_text(format!("Original link: {}", meta.link)));
 
-        self.tg
+        let page = self
+            .tg
             .create_page(&PageCreate {
-                title: meta.name,
+                title: meta.name.clone(),
                 content,
                 author_name: self
                     .author_name
@@ -230,16 +726,73 @@ where
                     .or_else(|| meta.authors.map(|x| x.join(", "))),
                 author_url: self.author_url.clone(),
             })
-            .await
-            .map_err(Into::into)
+            .await?;
+
+        // save hashes to index
+        if let Some(index) = self.phash_index.as_ref() {
+            if !hashes.is_empty() {
+                let gallery = IndexedGallery {
+                    link: meta.link,
+                    title: meta.name,
+                    telegraph: page.url.clone(),
+                    hashes,
+                };
+                if let Err(e) = index.insert(gallery).await {
+                    tracing::error!("[phash] unable to save hashes: {e:?}");
+                }
+            }
+        }
+        Ok(page)
     }
 }
 
+/// Records whose title contains all keywords, ranked by matching whole words and
+/// title length. Keywords must be lowercase.
+fn rank_records(
+    records: Vec<Arc<CacheRecord>>,
+    keywords: &[String],
+    limit: usize,
+) -> Vec<Arc<CacheRecord>> {
+    let mut matched: Vec<_> = records
+        .into_iter()
+        .filter_map(|r| {
+            let title = r.title.to_lowercase();
+            if !keywords.iter().all(|k| title.contains(k.as_str())) {
+                return None;
+            }
+            let words: Vec<_> = title
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|w| !w.is_empty())
+                .collect();
+            let whole = keywords
+                .iter()
+                .filter(|k| words.contains(&k.as_str()))
+                .count();
+            Some((whole, r))
+        })
+        .collect();
+    matched.sort_by(|(a, ra), (b, rb)| {
+        b.cmp(a)
+            .then(ra.title.len().cmp(&rb.title.len()))
+            .then(ra.title.cmp(&rb.title))
+    });
+    matched.into_iter().take(limit).map(|(_, r)| r).collect()
+}
+
 impl Synchronizer {
     pub fn match_url_from_text(content: &str) -> Option<&str> {
         match_first_group(&URL_FROM_TEXT_RE, content)
     }
 
+    /// Match all urls in order.
+    pub fn match_urls_from_text(content: &str) -> Vec<&str> {
+        URL_FROM_TEXT_RE
+            .captures_iter(content)
+            .filter_map(|c| c.get(1))
+            .map(|m| m.as_str())
+            .collect()
+    }
+
     pub fn match_url_from_url(content: &str) -> Option<&str> {
         match_first_group(&URL_FROM_URL_RE, content)
     }
@@ -263,3 +816,36 @@ impl From<UploadedImage> for Node {
         Node::new_image(format!("https://telegra.ph{}", i.src))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank() {
+        let records: Vec<_> = [
+            "[Circle] Long Title Part 2",
+            "[Circle] Title",
+            "Subtitle",
+            "Another Story",
+        ]
+        .into_iter()
+        .map(|title| {
+            Arc::new(CacheRecord {
+                url: String::new(),
+                title: title.to_string(),
+                link: String::new(),
+            })
+        })
+        .collect();
+        let keywords = vec!["title".to_string()];
+        let titles: Vec<_> = rank_records(records, &keywords, 10)
+            .into_iter()
+            .map(|r| r.title.clone())
+            .collect();
+        assert_eq!(
+            titles,
+            vec!["[Circle] Title", "[Circle] Long Title Part 2", "Subtitle"]
+        );
+    }
+}
@@ -13,7 +13,10 @@ use reqwest::{
 };
 use serde::Serialize;
 
-use crate::http_proxy::HttpRequestBuilder;
+use crate::{
+    http_proxy::HttpRequestBuilder,
+    metrics::{self, ObserveExt},
+};
 
 use self::{
     error::{ApiResult, UploadResult},
@@ -80,6 +83,7 @@ macro_rules! execute {
         $send
             .send()
             .await
+            .observe()
             .and_then(Response::error_for_status)?
             .json::<ApiResult<_>>()
             .await?
@@ -190,12 +194,14 @@ where
             cnt += 1;
         }
 
+        let _timer = metrics::TELEGRAPH_UPLOAD_DURATION.start_timer();
         let r: Result<Vec<MediaInfo>, TelegraphError> = self
             .client
             .post_builder("https://telegra.ph/upload")
             .multipart(form)
             .send()
             .await
+            .observe()
             .and_then(Response::error_for_status)?
             .json::<UploadResult>()
             .await?
@@ -2,7 +2,7 @@ use bytes::Bytes;
 use regex::Regex;
 use reqwest::Response;
 
-use crate::http_proxy::HttpRequestBuilder;
+use crate::{http_proxy::HttpRequestBuilder, metrics::ObserveExt};
 
 #[inline]
 pub fn match_first_group<'a>(regexp: &'a Regex, content: &'a str) -> Option<&'a str> {
@@ -19,6 +19,7 @@ pub async fn get_bytes<C: HttpRequestBuilder>(client: &C, link: &str) -> reqwest
         .get_builder(link)
         .send()
         .await
+        .observe()
         .and_then(Response::error_for_status)?
         .bytes()
         .await
@@ -30,6 +31,7 @@ pub async fn get_string<C: HttpRequestBuilder>(client: &C, link: &str) -> reqwes
         .get_builder(link)
         .send()
         .await
+        .observe()
         .and_then(Response::error_for_status)?
         .text()
         .await
@@ -0,0 +1,71 @@
+/*
+    Cloudflare workers KV proxy.
+    Compatible with cloudflare-kv-proxy, with key listing support.
+    Deploy, bind a KV namespace as `KV` and set `KEY` variable in browser.
+    Keys are the full path of the request, so it can be deployed under a path prefix.
+    List keys with `GET ?list=1&prefix=...&cursor=...`.
+*/
+
+addEventListener('fetch', event => {
+    event.respondWith(handleRequest(event.request))
+})
+
+const RESPONSE_HEADERS = {
+    "Server": "kv-proxy",
+    "Content-Type": "application/json",
+};
+
+function respond(status, body) {
+    return new Response(JSON.stringify(body), {
+        status: status,
+        headers: RESPONSE_HEADERS
+    });
+}
+
+async function handleRequest(request) {
+    // validate request key
+    if (request.headers.get("Authorization") != KEY) {
+        return respond(401, { code: 401, error: "unauthorized" });
+    }
+
+    var url = new URL(request.url);
+    var key = decodeURIComponent(url.pathname.slice(1));
+
+    if (url.searchParams.get("list") == "1") {
+        if (request.method != "GET") {
+            return respond(400, { code: 400, error: "unsupported method" });
+        }
+        var options = { prefix: url.searchParams.get("prefix") || "" };
+        var cursor = url.searchParams.get("cursor");
+        if (cursor != null && cursor != "") {
+            options.cursor = cursor;
+        }
+        var list = await KV.list(options);
+        return respond(200, { result: JSON.stringify(list) });
+    }
+    if (key == "") {
+        return respond(400, { code: 400, error: "key is required" });
+    }
+
+    switch (request.method) {
+        case "GET":
+            var value = await KV.get(key);
+            if (value == null) {
+                return respond(404, { code: 404, error: "key not found" });
+            }
+            return respond(200, { result: value });
+        case "PUT":
+            var ttl = request.headers.get("ttl");
+            var options = {};
+            if (ttl != null && ttl != "") {
+                options.expirationTtl = parseInt(ttl);
+            }
+            await KV.put(key, await request.text(), options);
+            return respond(200, { result: "null" });
+        case "DELETE":
+            await KV.delete(key);
+            return respond(200, { result: "null" });
+        default:
+            return respond(400, { code: 400, error: "unsupported method" });
+    }
+}